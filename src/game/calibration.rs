@@ -0,0 +1,100 @@
+use super::*;
+
+use crate::sound::{LatencyCalibration, Mixer, MixerOutput};
+
+/// Tempo of the calibration metronome.
+const CALIBRATION_BPM: f32 = 100.0;
+
+/// Screen where the player taps along to a metronome
+/// to measure the latency between the music and the input.
+pub struct Calibration {
+    geng: Geng,
+    settings: Rc<RefCell<Settings>>,
+    calibration: LatencyCalibration,
+    output: Option<MixerOutput>,
+    done: bool,
+}
+
+impl Calibration {
+    pub fn new(geng: &Geng, settings: &Rc<RefCell<Settings>>) -> Self {
+        let mixer = Mixer::new(synthesize::SAMPLE_RATE as u32);
+        let output = match mixer.play(geng) {
+            Ok(output) => Some(output),
+            Err(error) => {
+                log::error!("Failed to play the metronome: {error}");
+                None
+            }
+        };
+        Self {
+            geng: geng.clone(),
+            settings: settings.clone(),
+            calibration: LatencyCalibration::new(mixer, CALIBRATION_BPM),
+            output,
+            done: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        let Some(offset) = self.calibration.offset() else {
+            return;
+        };
+        log::info!("Calibrated input offset: {:.0}ms", offset * 1000.0);
+        let mut settings = self.settings.borrow_mut();
+        settings.input_offset = offset;
+        settings.save();
+    }
+}
+
+impl geng::State for Calibration {
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Rgba::BLACK), None, None);
+    }
+
+    fn update(&mut self, delta_time: f64) {
+        if let Some(output) = &mut self.output {
+            output.update(delta_time as f32);
+        }
+        self.calibration.update();
+        if self.calibration.is_finished() && !self.done {
+            self.finish();
+        }
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        match event {
+            geng::Event::KeyDown {
+                key: geng::Key::Escape,
+            } => {
+                // Cancel, keeping the old offset
+                self.done = true;
+            }
+            geng::Event::KeyDown { .. } | geng::Event::MouseDown { .. } => {
+                self.calibration.tap();
+            }
+            _ => (),
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        self.done.then_some(geng::state::Transition::Pop)
+    }
+
+    fn ui<'a>(&mut self, _cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+
+        let (taps, total) = self.calibration.progress();
+        let offset = match self.calibration.offset() {
+            Some(offset) => format!("{:.0}ms", offset * 1000.0),
+            None => "-".to_owned(),
+        };
+        geng::ui::stack![geng::ui::Text::new(
+            format!("Tap along to the metronome ({taps}/{total}), offset: {offset}"),
+            self.geng.default_font().clone(),
+            20.0,
+            Rgba::WHITE
+        )
+        .align(vec2(0.5, 0.5))]
+        .boxed()
+    }
+}
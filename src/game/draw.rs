@@ -12,10 +12,17 @@ const ACTION_COLOR: Rgba<f32> = Rgba {
     b: 0.1,
     a: 1.0,
 };
+const INTENT_COLOR: Rgba<f32> = Rgba {
+    r: 0.4,
+    g: 0.05,
+    b: 0.05,
+    a: 1.0,
+};
 
 impl Game {
     pub fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) -> SystemResult<()> {
         self.draw_grid(framebuffer)?;
+        self.draw_intents(framebuffer)?;
         self.draw_hovered(framebuffer)?;
         self.draw_units(framebuffer)?;
         self.draw_projectiles(framebuffer)?;
@@ -54,8 +61,6 @@ impl Game {
     }
 
     fn draw_hovered(&self, framebuffer: &mut ugli::Framebuffer) -> SystemResult<()> {
-        let framebuffer_size = framebuffer.size().map(|x| x as f32);
-
         let player_items = self
             .world
             .units
@@ -86,7 +91,41 @@ impl Game {
         // let delta = hovered - player_pos;
         // player_pos + crate::util::vec_to_dir(delta.map(|x| x as f32));
 
-        for (mesh, color) in highlight
+        self.draw_cells(highlight, framebuffer);
+        Ok(())
+    }
+
+    /// Highlights the cells targeted by the units' intents.
+    fn draw_intents(&self, framebuffer: &mut ugli::Framebuffer) -> SystemResult<()> {
+        let mut highlight = Vec::new();
+        for (id, unit) in self.world.units.unit.iter() {
+            let Some(UnitIntent {
+                action: Action::UseItem(action),
+                input,
+            }) = unit.as_ref().and_then(|unit| unit.intent.as_ref())
+            else {
+                continue;
+            };
+            let items = self.world.units.held_items.get(id).expect("Unit not found");
+            let Some(item) = items.get_item(action.item) else {
+                continue;
+            };
+            if let Some(target) = item.on_use.aim.assist(&self.world, id, input.target)? {
+                highlight.push((target.find_pos(&self.world)?, INTENT_COLOR));
+            }
+        }
+
+        self.draw_cells(highlight, framebuffer);
+        Ok(())
+    }
+
+    fn draw_cells(
+        &self,
+        cells: Vec<(vec2<Coord>, Rgba<f32>)>,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        for (mesh, color) in cells
             .into_iter()
             .map(|(pos, color)| (cell_mesh(pos, &self.world.grid), color))
         {
@@ -113,8 +152,6 @@ impl Game {
                 },
             )
         }
-
-        Ok(())
     }
 
     fn draw_particles(&self, framebuffer: &mut ugli::Framebuffer) -> SystemResult<()> {
@@ -145,7 +182,7 @@ impl Game {
 
     fn draw_sound(&self, framebuffer: &mut ugli::Framebuffer) -> SystemResult<()> {
         let framebuffer_size = framebuffer.size().map(|x| x as f32);
-        let buffer = self.world.music_controller.get_buffer();
+        let mixer = self.world.music_controller.get_mixer();
 
         // Visualize beat timer
         let beat_time = (1.0 - self.world.player_beat_time.as_f32()).clamp_range(0.0..=1.0);
@@ -159,11 +196,30 @@ impl Game {
             ),
         );
 
+        // Visualize the position in the bar
+        let beats_per_bar = self.world.beat_controller.beats_per_bar();
+        let (_, current_beat) = self.world.beat_controller.get_bar_position();
+        let beat_size = framebuffer_size.x * 0.2 / beats_per_bar.max(1) as f32;
+        for beat in 0..beats_per_bar {
+            let pos =
+                framebuffer_size * vec2(0.4, 0.9) + vec2((beat as f32 + 0.5) * beat_size, 0.0);
+            let size = if beat == 0 { 0.4 } else { 0.3 } * beat_size;
+            let color = if beat == current_beat {
+                Rgba::WHITE
+            } else {
+                Rgba::GRAY
+            };
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                &geng::PixelPerfectCamera,
+                &draw2d::Quad::new(Aabb2::point(pos).extend_uniform(size / 2.0), color),
+            );
+        }
+
         // Visualize the wave with time
-        let mesh = audio_mesh(buffer.clone(), Rgba::GRAY, Rgba::opaque(0.5, 0.0, 0.5));
-        let matrix =
-            mat3::scale(vec2(1.0 / rodio::Source::sample_rate(buffer) as f32, 1.0) * 3000.0)
-                * mat3::translate(vec2(-0.5, 0.0));
+        let mesh = audio_mesh(&mixer.history(), Rgba::GRAY, Rgba::opaque(0.5, 0.0, 0.5));
+        let matrix = mat3::scale(vec2(1.0 / mixer.sample_rate() as f32, 1.0) * 3000.0)
+            * mat3::translate(vec2(-0.5, 0.0));
         let mesh = ugli::VertexBuffer::new_dynamic(self.geng.ugli(), mesh);
         ugli::draw(
             framebuffer,
@@ -201,10 +257,8 @@ impl Game {
             / 2.0;
         for (_, item) in &query_item!(self.world.projectiles) {
             let pos = item.world_position.map(FCoord::as_f32);
-            let color = match item.fraction {
-                Fraction::Player => Rgba::opaque(0.6, 1.0, 0.6),
-                Fraction::Enemy => Rgba::opaque(1.0, 0.6, 0.6),
-            };
+            // Lighter than the units
+            let color = Rgba::lerp(self.world.fractions.color(*item.fraction), Rgba::WHITE, 0.6);
             self.geng.draw2d().draw2d(
                 framebuffer,
                 &self.camera,
@@ -219,6 +273,7 @@ impl Game {
         #[derive(StructQuery)]
         struct Item<'a> {
             grid_position: &'a vec2<Coord>,
+            footprint: &'a Footprint,
             unit: &'a Option<UnitAI>,
             fraction: &'a Fraction,
         }
@@ -233,20 +288,22 @@ impl Game {
                 .as_f32()
             / 2.0;
         for (id, item) in &query_item!(self.world.units) {
-            let &pos = item.grid_position;
-            let pos = self.world.grid.grid_to_world(pos).map(FCoord::as_f32);
+            // Cover the whole footprint
+            let grid = &self.world.grid;
+            let bounds = item.footprint.bounds();
+            let min = grid.grid_to_world(*item.grid_position + bounds.min);
+            let max = grid.grid_to_world(*item.grid_position + bounds.max);
+            let pos = ((min + max) / FCoord::new(2.0)).map(FCoord::as_f32);
+            let size = (bounds.size() + vec2(1, 1)).map(|x| x as f32);
             let color = if id == self.world.player.unit {
                 Rgba::BLUE
             } else {
-                match item.fraction {
-                    Fraction::Player => Rgba::GREEN,
-                    Fraction::Enemy => Rgba::RED,
-                }
+                self.world.fractions.color(*item.fraction)
             };
             self.geng.draw2d().draw2d(
                 framebuffer,
                 &self.camera,
-                &draw2d::Ellipse::circle(pos, radius * 0.9, color),
+                &draw2d::Ellipse::new(pos, size * radius * 0.9, color),
             );
 
             let beat_time = if id == self.world.player.unit {
@@ -290,17 +347,14 @@ fn cell_mesh(pos: vec2<Coord>, grid: &Grid) -> Vec<vec2<f32>> {
     ]
 }
 
+/// Constructs a mesh of the mono audio samples.
 pub fn audio_mesh(
-    source: impl rodio::Source<Item = f32>,
+    samples: &[f32],
     top_color: Rgba<f32>,
     bottom_color: Rgba<f32>,
 ) -> Vec<draw2d::ColoredVertex> {
-    if source.channels() != 1 {
-        unimplemented!("Only mono audio is supported");
-    }
-
     construct_points_mesh(
-        source.enumerate().map(|(x, y)| vec2(x as f32, y)),
+        samples.iter().enumerate().map(|(x, &y)| vec2(x as f32, y)),
         0.0,
         top_color,
         bottom_color,
@@ -0,0 +1,35 @@
+use super::*;
+
+/// Shows the problem that prevents the game from starting.
+pub struct ErrorScreen {
+    geng: Geng,
+    message: String,
+}
+
+impl ErrorScreen {
+    pub fn new(geng: &Geng, message: String) -> Self {
+        Self {
+            geng: geng.clone(),
+            message,
+        }
+    }
+}
+
+impl geng::State for ErrorScreen {
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Rgba::BLACK), None, None);
+    }
+
+    fn ui<'a>(&mut self, _cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+
+        geng::ui::stack![geng::ui::Text::new(
+            self.message.clone(),
+            self.geng.default_font().clone(),
+            20.0,
+            Rgba::RED
+        )
+        .align(vec2(0.5, 0.5))]
+        .boxed()
+    }
+}
@@ -3,12 +3,41 @@ use geng::{prelude::*, Camera2d};
 
 use crate::{
     assets::Assets,
-    sound::{MusicConfig, SectionName, Synthesizer},
+    settings::Settings,
+    sound::{synthesize, InvalidConfig, MusicConfig, Synthesizers, SCHEDULE_LATENCY},
     util::Report,
     world::*,
 };
 
+mod calibration;
 mod draw;
+mod error;
+
+use calibration::Calibration;
+use error::ErrorScreen;
+
+/// Problems that prevent the game from starting.
+#[derive(thiserror::Error, Debug)]
+enum LoadError {
+    #[error("failed to load the music config: {0}")]
+    MusicConfig(String),
+    #[error("failed to load the fractions: {0}")]
+    Fractions(String),
+    #[error("invalid fractions: {0}")]
+    InvalidFractions(#[from] FractionError),
+    #[error("failed to load the units: {0}")]
+    Units(String),
+    #[error("failed to load the spawner config: {0}")]
+    Spawner(String),
+    #[error("invalid spawner config: {0}")]
+    InvalidSpawner(#[from] SpawnerError),
+    #[error("invalid music config: {0}")]
+    InvalidConfig(#[from] InvalidConfig),
+    #[error("failed to load the soundfont {path:?}: {error}")]
+    SoundFont { path: String, error: String },
+    #[error("failed to create the synthesizers: {0}")]
+    Synthesize(#[from] synthesize::SynthesizeError),
+}
 
 pub struct Game {
     geng: Geng,
@@ -18,6 +47,9 @@ pub struct Game {
     framebuffer_size: vec2<usize>,
     cursor_world_pos: vec2<f32>,
     action: Option<(PlayerAction, ActionInput)>,
+    settings: Rc<RefCell<Settings>>,
+    /// Whether the latency calibration screen should be opened.
+    open_calibration: bool,
 }
 
 impl Game {
@@ -25,12 +57,22 @@ impl Game {
         geng: &Geng,
         assets: &Rc<Assets>,
         music_config: MusicConfig,
-        synthesizers: HashMap<SectionName, Synthesizer>,
+        synthesizers: Synthesizers,
+        fractions: Fractions,
+        prefabs: &UnitPrefabs,
+        spawner: Spawner,
     ) -> Self {
         Self {
             geng: geng.clone(),
             assets: assets.clone(),
-            world: World::new(geng, music_config, synthesizers),
+            world: World::new(
+                geng,
+                music_config,
+                synthesizers,
+                fractions,
+                prefabs,
+                spawner,
+            ),
             camera: Camera2d {
                 center: vec2::ZERO,
                 rotation: 0.0,
@@ -39,6 +81,8 @@ impl Game {
             framebuffer_size: vec2(1, 1),
             cursor_world_pos: vec2::ZERO,
             action: None,
+            settings: Rc::new(RefCell::new(Settings::load())),
+            open_calibration: false,
         }
     }
 
@@ -77,6 +121,13 @@ impl geng::State for Game {
 
         let delta_time = crate::world::Time::new(delta_time);
 
+        // The offset may have been changed on the calibration screen.
+        // The calibration clicks play right away, while the music is heard
+        // `SCHEDULE_LATENCY` after the beat controller's beat.
+        self.world
+            .beat_controller
+            .set_input_offset(self.settings.borrow().input_offset + SCHEDULE_LATENCY);
+
         self.world
             .update(self.action.take(), delta_time)
             .report_err();
@@ -90,6 +141,19 @@ impl geng::State for Game {
                     geng::Key::S => Some(vec2(0, -1)),
                     geng::Key::A => Some(vec2(-1, 0)),
                     geng::Key::D => Some(vec2(1, 0)),
+                    geng::Key::C => {
+                        self.open_calibration = true;
+                        None
+                    }
+                    geng::Key::Q => {
+                        self.world.swap_player_item();
+                        None
+                    }
+                    geng::Key::M => {
+                        let music = &mut self.world.music_controller;
+                        music.set_metronome_enabled(!music.is_metronome_enabled());
+                        None
+                    }
                     _ => None,
                 };
                 if let Some(delta) = delta {
@@ -111,11 +175,31 @@ impl geng::State for Game {
         }
     }
 
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        std::mem::take(&mut self.open_calibration).then(|| {
+            geng::state::Transition::Push(Box::new(Calibration::new(&self.geng, &self.settings)))
+        })
+    }
+
     fn ui<'a>(&mut self, _cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         use geng::ui::*;
 
+        let (bar, beat) = self.world.beat_controller.get_bar_position();
+        let wave = if self.world.spawner.is_finished() {
+            "cleared".to_owned()
+        } else {
+            (self.world.spawner.wave() + 1).to_string()
+        };
         geng::ui::stack![geng::ui::Text::new(
-            format!("BPM: {:.0}", self.world.beat_controller.get_bpm()),
+            format!(
+                "BPM: {:.0}  Bar {} beat {}/{}  Wave {}  Score {}",
+                self.world.beat_controller.get_bpm(),
+                bar + 1,
+                beat + 1,
+                self.world.beat_controller.beats_per_bar(),
+                wave,
+                self.world.score
+            ),
             self.geng.default_font().clone(),
             10.0,
             Rgba::WHITE
@@ -130,42 +214,91 @@ pub fn run(geng: &Geng) -> impl geng::State {
     let future = {
         let geng = geng.clone();
         async move {
-            let assets: Rc<Assets> =
-                geng::Load::load(geng.asset_manager(), &run_dir().join("assets"))
-                    .await
-                    .expect("Failed to load assets");
-
-            let config: MusicConfig = geng::Load::load(
-                geng.asset_manager(),
-                &run_dir().join("assets").join("config.json"),
-            )
-            .await
-            .expect("Failed to load music config");
-
-            let mut soundfonts = HashMap::new();
-            for (sf_name, path) in &config.soundfonts {
-                let bytes = file::load_bytes(run_dir().join("assets").join(path))
-                    .await
-                    .expect("Failed to load soundfont");
-                let mut reader = std::io::BufReader::new(&bytes[..]);
-                let soundfont =
-                    rustysynth::SoundFont::new(&mut reader).expect("Failed to parse soundfont");
-                soundfonts.insert(sf_name.to_owned(), Arc::new(soundfont));
-            }
-
-            let mut synthesizers = HashMap::new();
-            let settings = rustysynth::SynthesizerSettings::new(44000);
-            for (section_name, section) in &config.sections {
-                let soundfont = soundfonts
-                    .get(&section.soundfont.name)
-                    .expect("Unknown soundfont");
-                let synthesizer = rustysynth::Synthesizer::new(soundfont, &settings)
-                    .expect("Failed to create a synthesizer");
-                synthesizers.insert(section_name.to_owned(), synthesizer);
+            match load(&geng).await {
+                Ok(game) => Box::new(game) as Box<dyn geng::State>,
+                Err(error) => {
+                    log::error!("{error}");
+                    Box::new(ErrorScreen::new(&geng, error.to_string()))
+                }
             }
-
-            Game::new(&geng, &assets, config, synthesizers)
         }
     };
     geng::LoadingScreen::new(geng, geng::EmptyLoadingScreen::new(geng), future)
 }
+
+async fn load(geng: &Geng) -> Result<Game, LoadError> {
+    let assets: Rc<Assets> = geng::Load::load(geng.asset_manager(), &run_dir().join("assets"))
+        .await
+        .expect("Failed to load assets");
+
+    let mut config: MusicConfig = geng::Load::load(
+        geng.asset_manager(),
+        &run_dir().join("assets").join("config.json"),
+    )
+    .await
+    .map_err(|error| LoadError::MusicConfig(error.to_string()))?;
+
+    let fractions: FractionsConfig = geng::Load::load(
+        geng.asset_manager(),
+        &run_dir().join("assets").join("fractions.json"),
+    )
+    .await
+    .map_err(|error| LoadError::Fractions(error.to_string()))?;
+    let fractions = Fractions::new(fractions)?;
+
+    let prefabs: UnitPrefabs = geng::Load::load(
+        geng.asset_manager(),
+        &run_dir().join("assets").join("units.json"),
+    )
+    .await
+    .map_err(|error| LoadError::Units(error.to_string()))?;
+
+    let spawner: SpawnerConfig = geng::Load::load(
+        geng.asset_manager(),
+        &run_dir().join("assets").join("spawner.json"),
+    )
+    .await
+    .map_err(|error| LoadError::Spawner(error.to_string()))?;
+    let spawner = Spawner::new(spawner, &fractions, &prefabs)?;
+
+    let mut midi_files = HashMap::new();
+    for path in config.midi_paths() {
+        let bytes = file::load_bytes(run_dir().join("assets").join(&path)).await;
+        midi_files.insert(path, bytes.map_err(|error| error.to_string()));
+    }
+    config.import_midi_sections(|path| {
+        midi_files
+            .get(path)
+            .cloned()
+            .expect("Midi file was not loaded")
+    });
+
+    config.skip_invalid_sections()?;
+
+    let mut soundfonts = HashMap::new();
+    for (sf_name, path) in &config.soundfonts {
+        let soundfont_error = |error: String| LoadError::SoundFont {
+            path: path.to_owned(),
+            error,
+        };
+        let bytes = file::load_bytes(run_dir().join("assets").join(path))
+            .await
+            .map_err(|error| soundfont_error(error.to_string()))?;
+        let mut reader = std::io::BufReader::new(&bytes[..]);
+        let soundfont = rustysynth::SoundFont::new(&mut reader)
+            .map_err(|error| soundfont_error(error.to_string()))?;
+        soundfonts.insert(sf_name.to_owned(), Arc::new(soundfont));
+    }
+
+    let synthesizers = synthesize::create_synthesizers(&config, &soundfonts)?;
+
+    Ok(Game::new(
+        geng,
+        &assets,
+        config,
+        synthesizers,
+        fractions,
+        &prefabs,
+        spawner,
+    ))
+}
@@ -5,14 +5,37 @@ use geng::prelude::*;
 mod assets;
 mod collection;
 mod game;
+mod render;
+mod settings;
 mod sound;
 mod util;
 mod world;
 
+#[derive(clap::Parser)]
+struct Opts {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Render the music into a WAV file without running the game.
+    Render(render::RenderArgs),
+}
+
 fn main() {
     logger::init();
     geng::setup_panic_handler();
 
+    let opts: Opts = clap::Parser::parse();
+    if let Some(Command::Render(args)) = opts.command {
+        if let Err(error) = render::run(args) {
+            log::error!("{error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let geng = Geng::new_with(geng::ContextOptions {
         title: "Untitled Pseudo-Rhythm Game".to_string(),
         ..default()
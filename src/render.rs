@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use geng::prelude::*;
+
+use crate::sound::{import_midi, synthesize, InvalidConfig, Mixer, MusicConfig, MusicController};
+
+/// Simulation step of the render in seconds.
+const STEP: f32 = 0.01;
+
+#[derive(clap::Args, Debug)]
+pub struct RenderArgs {
+    /// Path to the music config.
+    #[arg(long, default_value = "assets/config.json")]
+    pub config: PathBuf,
+    /// BPM over time as comma separated `time:bpm` points, e.g. `0:60,30:120`.
+    /// BPM is interpolated linearly between the points.
+    #[arg(long, default_value = "80")]
+    pub bpm: BpmCurve,
+    /// Duration of the render in seconds.
+    #[arg(long, default_value_t = 30.0)]
+    pub duration: f32,
+    /// Path to the output WAV file.
+    #[arg(long, short, default_value = "render.wav")]
+    pub output: PathBuf,
+}
+
+/// Piecewise linear BPM over time.
+#[derive(Debug, Clone)]
+pub struct BpmCurve {
+    /// Sorted by time.
+    points: Vec<(f32, f32)>,
+}
+
+pub type RenderResult<T> = Result<T, RenderError>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RenderError {
+    #[error("failed to read {path:?}: {error}")]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("failed to parse the music config: {0}")]
+    Config(#[from] serde_json::Error),
+    #[error("invalid music config: {0}")]
+    InvalidConfig(#[from] InvalidConfig),
+    #[error("failed to parse the soundfont {path:?}: {error}")]
+    SoundFont {
+        path: PathBuf,
+        error: rustysynth::SoundFontError,
+    },
+    #[error("failed to create the synthesizers: {0}")]
+    Synthesize(#[from] synthesize::SynthesizeError),
+    #[error("failed to write the output: {0}")]
+    Wav(#[from] hound::Error),
+}
+
+impl BpmCurve {
+    pub fn get(&self, time: f32) -> f32 {
+        let i = self.points.partition_point(|&(t, _)| t <= time);
+        match (i.checked_sub(1).map(|i| self.points[i]), self.points.get(i)) {
+            (Some((t0, bpm0)), Some(&(t1, bpm1))) => {
+                let t = (time - t0) / (t1 - t0);
+                bpm0 + (bpm1 - bpm0) * t
+            }
+            (Some((_, bpm)), None) | (None, Some(&(_, bpm))) => bpm,
+            (None, None) => unreachable!("BPM curve cannot be empty"),
+        }
+    }
+}
+
+impl std::str::FromStr for BpmCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<f32>()
+                .map_err(|_| format!("invalid number {s:?}"))
+        };
+        let mut points = s
+            .split(',')
+            .map(|point| match point.split_once(':') {
+                Some((time, bpm)) => Ok((parse(time)?, parse(bpm)?)),
+                None => Ok((0.0, parse(point)?)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if points.iter().any(|&(_, bpm)| bpm <= 0.0) {
+            return Err("BPM must be positive".to_owned());
+        }
+        points.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(Self { points })
+    }
+}
+
+/// Renders the music into a WAV file without running the game.
+pub fn run(args: RenderArgs) -> RenderResult<()> {
+    let assets_dir = args.config.parent().unwrap_or(Path::new("."));
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|error| RenderError::Io {
+            path: path.to_owned(),
+            error,
+        })
+    };
+
+    let mut config: MusicConfig = serde_json::from_slice(&read(&args.config)?)?;
+
+    let ticks_per_beat = config.ticks_per_beat;
+    config.import_midi_sections(|path| std::fs::read(assets_dir.join(path)));
+
+    config.skip_invalid_sections()?;
+
+    let mut soundfonts = HashMap::new();
+    for (sf_name, path) in &config.soundfonts {
+        let path = assets_dir.join(path);
+        let bytes = read(&path)?;
+        let soundfont = rustysynth::SoundFont::new(&mut std::io::BufReader::new(&bytes[..]))
+            .map_err(|error| RenderError::SoundFont { path, error })?;
+        soundfonts.insert(sf_name.to_owned(), Arc::new(soundfont));
+    }
+    let synthesizers = synthesize::create_synthesizers(&config, &soundfonts)?;
+
+    // The mixer is driven manually instead of by an audio device
+    let sample_rate = synthesize::SAMPLE_RATE as u32;
+    let mixer = Mixer::new(sample_rate);
+    let mut music = MusicController::new(config, args.bpm.get(0.0), synthesizers, mixer.clone());
+    let step_samples = (sample_rate as f32 * STEP) as usize;
+
+    let mut output = Vec::new();
+    let mut time = 0.0;
+    let mut next_tick = 0.0;
+    while time < args.duration {
+        let bpm = args.bpm.get(time);
+        music.set_bpm(bpm);
+
+        let tick_t = 60.0 / (bpm * ticks_per_beat as f32);
+        next_tick -= STEP / tick_t;
+        while next_tick < 0.0 {
+            music.tick(STEP + next_tick * tick_t);
+            next_tick += 1.0;
+        }
+        music.update(STEP);
+
+        let start = output.len();
+        output.resize(start + step_samples, 0.0);
+        mixer.render(&mut output[start..]);
+
+        time += STEP;
+    }
+
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&args.output, spec)?;
+    for sample in output {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    log::info!(
+        "Rendered {:.1} seconds into {:?}",
+        args.duration,
+        args.output
+    );
+    Ok(())
+}
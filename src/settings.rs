@@ -0,0 +1,22 @@
+use geng::prelude::*;
+
+const SETTINGS_KEY: &str = "pseudo-rhythm-game/settings";
+
+/// User settings preserved between runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Delay of the player's input relative to the music in seconds,
+    /// measured on the calibration screen.
+    pub input_offset: f32,
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        preferences::load(SETTINGS_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        preferences::save(SETTINGS_KEY, self);
+    }
+}
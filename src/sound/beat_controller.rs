@@ -1,12 +1,59 @@
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
 use super::config::Ticks;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct BeatConfig {
     pub bpm_min: Ticks,
     pub bpm_max: Ticks,
+    /// Taken from `MusicConfig::ticks_per_beat`.
+    #[serde(skip)]
     pub ticks_per_beat: Ticks,
+    /// Taken from `MusicConfig::meter`.
+    #[serde(skip)]
+    pub beats_per_bar: Ticks,
     pub miss_time_early: f32,
     pub miss_time_late: f32,
+    pub tempo: TempoMode,
+    pub smoothing: BpmSmoothing,
+    /// Maximum change of BPM per player's beat.
+    pub max_bpm_change: Option<f32>,
+}
+
+/// Describes who controls the BPM.
+#[derive(Debug, Clone, Deserialize)]
+pub enum TempoMode {
+    /// The BPM follows the timings of the player's beats.
+    Follow,
+    /// The BPM is fixed by the level and the player has to match it.
+    Locked { bpm: f32 },
+}
+
+/// Describes how the BPM approaches the player's tempo.
+#[derive(Debug, Clone, Deserialize)]
+pub enum BpmSmoothing {
+    /// Moves towards the player's tempo by `factor` of the difference every beat.
+    Exponential { factor: f32 },
+    /// Averages the player's tempo over the last `window` beats.
+    MovingAverage { window: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatJudgement {
+    /// The beat fits the music.
+    Hit,
+    /// The beat is too far from the music's beat, only happens in `TempoMode::Locked`.
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerBeat {
+    pub judgement: BeatJudgement,
+    /// Number of ticks that should be skipped.
+    pub skip_ticks: Ticks,
 }
 
 #[derive(Debug, Clone)]
@@ -27,18 +74,30 @@ pub struct BeatController {
     tick_t: f32,
     /// Normalized (in range 0..1) time until the next tick.
     next_tick: f32,
+    /// Player's tempo at the last beats, used for `BpmSmoothing::MovingAverage`.
+    bpm_history: VecDeque<f32>,
+    /// Delay of the player's input relative to the music in seconds.
+    input_offset: f32,
 }
 
 impl BeatController {
-    pub fn new(config: BeatConfig) -> Self {
+    /// Creates a controller starting at the given `bpm`.
+    /// In `TempoMode::Locked` the locked BPM is used instead.
+    pub fn new(config: BeatConfig, bpm: f32) -> Self {
+        let bpm = match config.tempo {
+            TempoMode::Follow => bpm.clamp(config.bpm_min as f32, config.bpm_max as f32),
+            TempoMode::Locked { bpm } => bpm,
+        };
         Self {
             last_beat: 0.0,
             last_player_beat: 0.0,
-            last_player_bpm: 0.0,
+            last_player_bpm: bpm,
             tick: 0,
-            current_bpm: config.bpm_min as f32,
+            current_bpm: bpm,
             tick_t: 1.0,
             next_tick: 1.0,
+            bpm_history: VecDeque::new(),
+            input_offset: 0.0,
             config,
         }
     }
@@ -48,59 +107,132 @@ impl BeatController {
         self.current_bpm
     }
 
+    /// Sets the delay of the player's input relative to the music,
+    /// measured by `LatencyCalibration`.
+    pub fn set_input_offset(&mut self, offset: f32) {
+        self.input_offset = offset;
+    }
+
+    /// Returns the current tick number.
+    pub fn get_tick(&self) -> Ticks {
+        self.tick
+    }
+
+    pub fn ticks_per_beat(&self) -> Ticks {
+        self.config.ticks_per_beat
+    }
+
+    pub fn beats_per_bar(&self) -> Ticks {
+        self.config.beats_per_bar
+    }
+
+    pub fn ticks_per_bar(&self) -> Ticks {
+        self.config.ticks_per_beat * self.config.beats_per_bar
+    }
+
+    /// Returns the current bar number and the index of the beat within the bar.
+    pub fn get_bar_position(&self) -> (Ticks, Ticks) {
+        let beat = self.tick / self.config.ticks_per_beat;
+        (
+            beat / self.config.beats_per_bar,
+            beat % self.config.beats_per_bar,
+        )
+    }
+
+    /// Returns the normalized (in range 0..1) time since the current tick.
+    pub fn get_tick_progress(&self) -> f32 {
+        1.0 - self.next_tick.clamp(0.0, 1.0)
+    }
+
+    /// Returns the normalized (in range 0..1) time since the start of the current bar.
+    pub fn get_bar_progress(&self) -> f32 {
+        let ticks_per_bar = self.ticks_per_bar();
+        let ticks = (self.tick % ticks_per_bar) as f32 + self.get_tick_progress();
+        ticks / ticks_per_bar as f32
+    }
+
     /// Update the controller.
-    /// Returns the number of ticks that happen in the `delta_time`.
-    pub fn update(&mut self, delta_time: f32) -> Ticks {
+    /// Returns the times of the ticks that happen in the `delta_time`,
+    /// relative to the start of the update.
+    pub fn update(&mut self, delta_time: f32) -> Vec<f32> {
         self.last_beat += delta_time;
         self.last_player_beat += delta_time;
 
-        if self.last_player_beat > 60.0 / self.current_bpm + self.config.miss_time_late {
-            // Late player beat -> slow down
-            let target_bpm = 60.0 / self.last_player_beat;
-            self.current_bpm = tween_bpm(self.last_player_bpm, target_bpm, &self.config);
+        if let TempoMode::Follow = self.config.tempo {
+            if self.last_player_beat > 60.0 / self.current_bpm + self.config.miss_time_late {
+                // Late player beat -> slow down
+                let target_bpm = 60.0 / self.last_player_beat;
+                self.current_bpm = self.smooth_bpm(target_bpm);
+            }
         }
 
         // Music tick
         self.tick_t = 60.0 / (self.current_bpm * self.config.ticks_per_beat as f32);
         self.next_tick -= delta_time / self.tick_t;
 
-        let mut ticks = 0;
+        let mut ticks = Vec::new();
         while self.next_tick < 0.0 {
             self.tick();
-            ticks += 1;
+            ticks.push((delta_time + self.next_tick * self.tick_t).max(0.0));
             self.next_tick += 1.0;
         }
         ticks
     }
 
     /// Player inputs the beat event.
-    /// Updates the BPM to match the timings between inputs.
-    /// Returns the number of ticks that should be skipped.
-    pub fn player_beat(&mut self) -> Ticks {
+    /// In `TempoMode::Follow` updates the BPM to match the timings between inputs,
+    /// in `TempoMode::Locked` judges the timing of the input.
+    pub fn player_beat(&mut self) -> PlayerBeat {
         // Early action
         let next_tick = self.next_tick * self.tick_t;
         let next_beat = next_tick
             + (self.config.ticks_per_beat - 1 - self.tick % self.config.ticks_per_beat) as f32
                 * self.tick_t;
 
+        // The player meant to act `input_offset` seconds earlier
+        let next_beat = next_beat + self.input_offset;
+        let last_beat = self.last_beat - self.input_offset;
+
+        let hit = PlayerBeat {
+            judgement: BeatJudgement::Hit,
+            skip_ticks: 0,
+        };
+
+        if let TempoMode::Locked { .. } = self.config.tempo {
+            let judgement = if next_beat < self.config.miss_time_early
+                || last_beat < self.config.miss_time_late
+            {
+                BeatJudgement::Hit
+            } else {
+                BeatJudgement::Miss
+            };
+            return PlayerBeat {
+                judgement,
+                skip_ticks: 0,
+            };
+        }
+
         if next_beat < self.config.miss_time_early {
             // Early action
             self.last_player_beat += next_beat;
             self.update_bpm();
             self.last_player_beat = -next_beat;
-            return 0;
+            return hit;
         }
 
-        if self.last_beat < self.config.miss_time_late {
+        if last_beat < self.config.miss_time_late {
             // Late action
-            self.last_player_beat -= self.last_beat;
+            self.last_player_beat -= last_beat;
             self.update_bpm();
-            self.last_player_beat = self.last_beat;
-            return 0;
+            self.last_player_beat = last_beat;
+            return hit;
         }
 
         self.update_bpm();
-        self.skip_to_next_beat()
+        PlayerBeat {
+            skip_ticks: self.skip_to_next_beat(),
+            ..hit
+        }
     }
 
     fn tick(&mut self) {
@@ -118,14 +250,37 @@ impl BeatController {
         }
 
         let target_bpm = 60.0 / self.last_player_beat;
-        self.current_bpm = tween_bpm(self.last_player_bpm, target_bpm, &self.config);
+        self.current_bpm = self.smooth_bpm(target_bpm);
         self.last_player_bpm = self.current_bpm;
         self.last_player_beat = 0.0;
+
+        if let BpmSmoothing::MovingAverage { window } = self.config.smoothing {
+            self.bpm_history.push_back(target_bpm);
+            while self.bpm_history.len() >= window.max(1) {
+                self.bpm_history.pop_front();
+            }
+        }
+    }
+
+    /// Calculates the new BPM from the player's tempo according to the config.
+    fn smooth_bpm(&self, target: f32) -> f32 {
+        let current = self.last_player_bpm;
+        let mut bpm = match self.config.smoothing {
+            BpmSmoothing::Exponential { factor } => (1.0 - factor) * current + factor * target,
+            BpmSmoothing::MovingAverage { .. } => {
+                let sum: f32 = self.bpm_history.iter().sum();
+                (sum + target) / (self.bpm_history.len() + 1) as f32
+            }
+        };
+        if let Some(max_change) = self.config.max_bpm_change {
+            bpm = bpm.clamp(current - max_change, current + max_change);
+        }
+        bpm.clamp(self.config.bpm_min as f32, self.config.bpm_max as f32)
     }
 
     /// Skips over ticks straight to the next beat.
+    /// The music in the skipped ticks is handled by `MusicController::skip_ticks`.
     pub fn skip_to_next_beat(&mut self) -> Ticks {
-        // TODO: manage skipped ticks
         self.next_tick = 0.0;
         let skip = self.config.ticks_per_beat - 1 - self.tick % self.config.ticks_per_beat;
         self.tick += skip;
@@ -139,13 +294,12 @@ impl Default for BeatConfig {
             bpm_min: 30,
             bpm_max: 240,
             ticks_per_beat: 4,
+            beats_per_bar: 4,
             miss_time_early: 0.1,
             miss_time_late: 0.2,
+            tempo: TempoMode::Follow,
+            smoothing: BpmSmoothing::Exponential { factor: 0.5 },
+            max_bpm_change: None,
         }
     }
 }
-
-fn tween_bpm(current: f32, target: f32, config: &BeatConfig) -> f32 {
-    let alpha = 0.5;
-    ((1.0 - alpha) * current + alpha * target).clamp(config.bpm_min as f32, config.bpm_max as f32)
-}
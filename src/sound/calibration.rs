@@ -0,0 +1,96 @@
+use super::{
+    mixer::{Mixer, SamplePos},
+    source::Beat,
+};
+
+/// Number of taps averaged to find the offset.
+const CALIBRATION_TAPS: usize = 16;
+/// Number of clicks to get into the rhythm before the taps are counted.
+const WARMUP_CLICKS: SamplePos = 4;
+/// How far ahead the clicks are scheduled, in seconds.
+const SCHEDULE_AHEAD: f32 = 0.2;
+
+/// Plays a metronome and measures the offset between its clicks and the player's taps.
+/// The offset accounts for both the audio output and the input latency.
+pub struct LatencyCalibration {
+    mixer: Mixer,
+    /// Time between the clicks in samples.
+    interval: SamplePos,
+    /// Position of the first click.
+    start: SamplePos,
+    /// Number of clicks scheduled so far.
+    clicks: SamplePos,
+    /// Offsets of the taps in seconds.
+    offsets: Vec<f32>,
+}
+
+impl LatencyCalibration {
+    pub fn new(mixer: Mixer, bpm: f32) -> Self {
+        let interval = (mixer.sample_rate() as f32 * 60.0 / bpm) as SamplePos;
+        Self {
+            start: mixer.position() + interval,
+            mixer,
+            interval,
+            clicks: 0,
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Schedules the upcoming clicks, should be called every frame.
+    pub fn update(&mut self) {
+        let sample_rate = self.mixer.sample_rate();
+        let horizon = self.mixer.position() + (sample_rate as f32 * SCHEDULE_AHEAD) as SamplePos;
+        loop {
+            let position = self.start + self.clicks * self.interval;
+            if position > horizon {
+                break;
+            }
+
+            let beat = if self.clicks % 4 == 0 {
+                // Major beat
+                Beat::default()
+            } else {
+                // Minor beat
+                Beat::new(150.0, 0.2)
+            };
+            self.mixer
+                .schedule(beat.with_sample_rate(sample_rate), position);
+            self.clicks += 1;
+        }
+    }
+
+    /// Registers the player's tap at the current moment.
+    pub fn tap(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+
+        let interval = self.interval as f32;
+        let beats = (self.mixer.position() as f32 - self.start as f32) / interval;
+        let nearest = beats.round();
+        if nearest < WARMUP_CLICKS as f32 {
+            return;
+        }
+
+        let offset = (beats - nearest) * interval / self.mixer.sample_rate() as f32;
+        self.offsets.push(offset);
+    }
+
+    /// Returns the number of taps registered and the number of taps needed.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.offsets.len(), CALIBRATION_TAPS)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.offsets.len() >= CALIBRATION_TAPS
+    }
+
+    /// Returns the average offset of the taps in seconds.
+    /// Positive offset means that the taps come after the clicks.
+    pub fn offset(&self) -> Option<f32> {
+        if self.offsets.is_empty() {
+            return None;
+        }
+        Some(self.offsets.iter().sum::<f32>() / self.offsets.len() as f32)
+    }
+}
@@ -1,18 +1,85 @@
 use geng::prelude::*;
 use std::collections::VecDeque;
 
+use super::{beat_controller::BeatConfig, sfx::SfxConfig};
+
 pub type SFName = String;
 pub type SectionName = String;
+pub type PatternName = String;
 pub type Ticks = u32;
 
+/// The highest note number in the midi standard.
+pub const MIDI_MAX: u8 = 127;
+
 #[derive(Debug, Clone, Deserialize, geng::Load)]
 #[load(json)]
 pub struct MusicConfig {
     pub soundfonts: HashMap<SFName, String>,
+    /// Starting BPM.
+    pub bpm: f32,
+    #[serde(default)]
+    pub beat: BeatConfig,
     pub ticks_per_beat: Ticks,
+    #[serde(default)]
+    pub meter: Meter,
+    #[serde(default)]
+    pub metronome: MetronomeConfig,
+    #[serde(default)]
+    pub sfx: SfxConfig,
+    /// What to do with the music in ticks skipped by an early player beat.
+    #[serde(default)]
+    pub skipped_ticks: SkipPolicy,
+    /// Reusable event sequences that sections can refer to by name.
+    #[serde(default)]
+    pub patterns: HashMap<PatternName, Vec<SectionEvent>>,
     pub sections: HashMap<SectionName, SectionConfig>,
 }
 
+/// Time signature of the music.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Meter {
+    pub beats_per_bar: Ticks,
+    /// Accent strength (in range 0..1) of each beat in the bar.
+    /// Beats not listed are unaccented.
+    pub accents: Vec<f32>,
+}
+
+/// Click track marking every beat of the music.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetronomeConfig {
+    pub enabled: bool,
+    /// Volume of the clicks in range 0..1.
+    pub volume: f32,
+    /// Play notes from a soundfont instead of the generated clicks.
+    pub sample: Option<MetronomeSample>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetronomeSample {
+    pub soundfont: SFConfig,
+    /// Note played on the accented beats.
+    pub accent: Note,
+    /// Note played on the other beats.
+    pub note: Note,
+    pub velocity: u32,
+}
+
+/// Describes how the music handles ticks skipped by an early player beat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SkipPolicy {
+    /// The events in the skipped ticks are not played.
+    #[default]
+    Drop,
+    /// The skipped ticks are played quickly one after another right before the next tick.
+    Compress,
+    /// The skipped ticks are spread between the following ticks,
+    /// so the music catches up over the next beats.
+    Stretch,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct SectionConfig {
     pub soundfont: SFConfig,
@@ -21,9 +88,26 @@ pub struct SectionConfig {
     pub key: Note,
     pub default_velocity: u32,
     pub default_duration: Ticks,
+    /// Only start the section on the first beat of a bar.
+    #[serde(default)]
+    pub on_downbeat: bool,
+    /// Import the events from a midi file instead of listing them in `events`.
+    pub midi: Option<MidiImport>,
+    #[serde(default)]
     pub events: VecDeque<SectionEvent>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "serder::MidiImportSerde")]
+pub struct MidiImport {
+    /// Path to the midi file relative to the assets directory.
+    pub path: String,
+    /// Index of the track to import. If not specified, all tracks are merged.
+    pub track: Option<usize>,
+    /// Only import the notes from this channel.
+    pub channel: Option<u8>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(from = "serder::SFConfigSerde")]
 pub struct SFConfig {
@@ -37,6 +121,7 @@ pub enum SectionEvent {
     Delay { delay: Ticks },
     NoteDefault(NoteSpec),
     Note(NoteOn),
+    Chord(ChordOn),
     Tagged(SectionEventTagged),
 }
 
@@ -44,8 +129,21 @@ pub enum SectionEvent {
 #[serde(tag = "type", deny_unknown_fields)]
 pub enum SectionEventTagged {
     // NoteOff { note: NoteSpec },
-    ChangeDefaultVelocity { value: u32 },
-    ChangeDefaultDuration { value: Ticks },
+    ChangeDefaultVelocity {
+        value: u32,
+    },
+    ChangeDefaultDuration {
+        value: Ticks,
+    },
+    /// Play the events `count` times in a row.
+    Repeat {
+        count: u32,
+        events: Vec<SectionEvent>,
+    },
+    /// Play the events of a pattern defined in `MusicConfig::patterns`.
+    Pattern {
+        name: PatternName,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
@@ -56,6 +154,68 @@ pub struct NoteOn {
     pub duration: Option<Ticks>,
 }
 
+/// A note played by a gameplay event in the key of the current section.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "serder::GameNoteSerde")]
+pub struct GameNote {
+    pub note: NoteSpec,
+    /// Soundfont program to play the note with, the section's one if not specified.
+    pub program: Option<u8>,
+    pub velocity: Option<u32>,
+    pub duration: Option<Ticks>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChordOn {
+    pub chord: ChordSpec,
+    /// Velocity for the notes that do not specify their own.
+    pub velocity: Option<u32>,
+    pub duration: Option<Ticks>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ChordSpec {
+    Notes(Vec<ChordNote>),
+    Name(ChordName),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum ChordNote {
+    Note(NoteSpec),
+    Full {
+        note: NoteSpec,
+        velocity: Option<u32>,
+    },
+}
+
+/// A chord described by its root and quality, e.g. `Cmaj7` or `F#3m`.
+/// If the octave of the root is omitted, the octave of the section's key is used.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChordName {
+    pub root: u8,
+    pub octave: Option<u8>,
+    pub quality: ChordQuality,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+}
+
 #[derive(Debug, Clone, Copy, Deserialize)]
 #[serde(untagged)]
 pub enum NoteSpec {
@@ -64,7 +224,7 @@ pub enum NoteSpec {
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
-#[serde(from = "serder::NoteSerde")]
+#[serde(try_from = "serder::NoteSerde")]
 pub struct Note {
     pub octave: u8,
     pub note: u8,
@@ -79,14 +239,135 @@ impl NoteSpec {
     }
 }
 
+impl ChordSpec {
+    /// Returns the notes of the chord with their velocities.
+    /// `velocity` is used for the notes that do not specify it explicitly.
+    pub fn to_notes(&self, key: Note, velocity: u32) -> Vec<(Note, u32)> {
+        match self {
+            Self::Notes(notes) => notes
+                .iter()
+                .map(|note| match *note {
+                    ChordNote::Note(note) => (note.to_note(key), velocity),
+                    ChordNote::Full {
+                        note,
+                        velocity: note_velocity,
+                    } => (note.to_note(key), note_velocity.unwrap_or(velocity)),
+                })
+                .collect(),
+            Self::Name(name) => name
+                .to_notes(key)
+                .into_iter()
+                .map(|note| (note, velocity))
+                .collect(),
+        }
+    }
+}
+
+impl ChordName {
+    pub fn to_notes(self, key: Note) -> Vec<Note> {
+        let root = Note {
+            octave: self.octave.unwrap_or(key.octave),
+            note: self.root,
+        };
+        self.quality
+            .intervals()
+            .iter()
+            .map(|&delta| root.shift(delta))
+            .collect()
+    }
+}
+
+impl ChordQuality {
+    /// Intervals of the chord's notes in semitones from the root.
+    pub fn intervals(self) -> &'static [i8] {
+        match self {
+            Self::Major => &[0, 4, 7],
+            Self::Minor => &[0, 3, 7],
+            Self::Diminished => &[0, 3, 6],
+            Self::Augmented => &[0, 4, 8],
+            Self::Sus2 => &[0, 2, 7],
+            Self::Sus4 => &[0, 5, 7],
+            Self::Dominant7 => &[0, 4, 7, 10],
+            Self::Major7 => &[0, 4, 7, 11],
+            Self::Minor7 => &[0, 3, 7, 10],
+            Self::HalfDiminished7 => &[0, 3, 6, 10],
+            Self::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+}
+
+impl std::str::FromStr for ChordQuality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let quality = match s {
+            "" | "maj" => Self::Major,
+            "m" | "min" => Self::Minor,
+            "dim" => Self::Diminished,
+            "aug" | "+" => Self::Augmented,
+            "sus2" => Self::Sus2,
+            "sus4" | "sus" => Self::Sus4,
+            "7" => Self::Dominant7,
+            "maj7" => Self::Major7,
+            "m7" | "min7" => Self::Minor7,
+            "m7b5" => Self::HalfDiminished7,
+            "dim7" => Self::Diminished7,
+            _ => return Err(()),
+        };
+        Ok(quality)
+    }
+}
+
+impl std::str::FromStr for ChordName {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The root is the first character and an optional `#`
+        let mut chars = s.char_indices().skip(1);
+        let root_len = match chars.next() {
+            Some((i, '#')) => i + 1,
+            Some((i, _)) => i,
+            None => s.len(),
+        };
+        let (root, rest) = s.split_at(root_len);
+        let root = note_from_name(root).ok_or(())?;
+
+        // Optional single digit octave right after the root, e.g. `C3maj7`.
+        // A lone `7` is the dominant seventh quality rather than an octave.
+        let (octave, quality) = match rest.chars().next() {
+            Some(c) if c.is_ascii_digit() && rest != "7" => {
+                (Some(c.to_digit(10).unwrap() as u8), &rest[1..])
+            }
+            _ => (None, rest),
+        };
+        let quality = quality.parse()?;
+
+        Ok(Self {
+            root,
+            octave,
+            quality,
+        })
+    }
+}
+
 impl Note {
+    /// Shifts the note by `delta` semitones, saturating at the bounds of the midi range.
     pub fn shift(self, delta: i8) -> Self {
-        let note_delta = delta.rem_euclid(12);
-        let octave_delta = (delta - note_delta) / 12;
-        Self {
-            octave: self.octave.saturating_add_signed(octave_delta),
-            note: self.note.saturating_add_signed(note_delta),
-        }
+        let midi = (self.to_midi() as i16 + delta as i16).clamp(0, MIDI_MAX as i16);
+        Self::from_midi(midi as u8)
+    }
+
+    /// Shifts the note by `delta` semitones.
+    /// Returns `None` if the result falls outside of the midi range.
+    pub fn checked_shift(self, delta: i8) -> Option<Self> {
+        let midi = self.to_midi_wide() + delta as i16;
+        (0..=MIDI_MAX as i16)
+            .contains(&midi)
+            .then(|| Self::from_midi(midi as u8))
+    }
+
+    pub fn is_valid_midi(self) -> bool {
+        self.note < 12 && self.to_midi_wide() <= MIDI_MAX as i16
     }
 
     pub fn from_midi(midi: u8) -> Self {
@@ -99,6 +380,11 @@ impl Note {
     pub fn to_midi(self) -> u8 {
         self.octave * 12 + self.note
     }
+
+    /// Same as `to_midi`, but does not overflow for high octaves.
+    fn to_midi_wide(self) -> i16 {
+        self.octave as i16 * 12 + self.note as i16
+    }
 }
 
 impl std::fmt::Display for Note {
@@ -123,39 +409,103 @@ impl std::fmt::Display for Note {
     }
 }
 
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum NoteParseError {
+    #[error("unrecognized note name {0:?}, expected a name like \"C#4\"")]
+    UnknownName(String),
+    #[error("invalid octave in note {0:?}")]
+    InvalidOctave(String),
+}
+
 impl std::str::FromStr for Note {
-    type Err = ();
+    type Err = NoteParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // TODO: Proper error message
-        let (note, octave) = match s.strip_suffix(|c: char| c.is_ascii_digit()) {
-            Some(note) => (note, s.get(s.len() - 1..).unwrap().parse().unwrap()),
-            None => (s, 4),
-        };
-        let note = match note {
-            "C" => 0,
-            "C#" => 1,
-            "D" => 2,
-            "D#" => 3,
-            "E" => 4,
-            "F" => 5,
-            "F#" => 6,
-            "G" => 7,
-            "G#" => 8,
-            "A" => 9,
-            "A#" => 10,
-            "B" => 11,
-            _ => return Err(()),
+        let split = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+        let (note, octave) = s.split_at(split);
+        let octave = if octave.is_empty() {
+            4
+        } else {
+            octave
+                .parse()
+                .map_err(|_| NoteParseError::InvalidOctave(s.to_owned()))?
         };
+        let note = note_from_name(note).ok_or_else(|| NoteParseError::UnknownName(s.to_owned()))?;
         Ok(Self { octave, note })
     }
 }
 
+fn note_from_name(name: &str) -> Option<u8> {
+    let note = match name {
+        "C" => 0,
+        "C#" => 1,
+        "D" => 2,
+        "D#" => 3,
+        "E" => 4,
+        "F" => 5,
+        "F#" => 6,
+        "G" => 7,
+        "G#" => 8,
+        "A" => 9,
+        "A#" => 10,
+        "B" => 11,
+        _ => return None,
+    };
+    Some(note)
+}
+
+impl MusicConfig {
+    /// Returns the beat config with `ticks_per_beat` filled in.
+    pub fn beat_config(&self) -> BeatConfig {
+        BeatConfig {
+            ticks_per_beat: self.ticks_per_beat,
+            beats_per_bar: self.meter.beats_per_bar,
+            ..self.beat.clone()
+        }
+    }
+}
+
+impl Meter {
+    /// Returns the accent strength of the beat with the given index in the bar.
+    pub fn accent(&self, beat_in_bar: Ticks) -> f32 {
+        self.accents
+            .get(beat_in_bar as usize)
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+impl Default for MetronomeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume: 0.5,
+            sample: None,
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            beats_per_bar: 4,
+            accents: vec![1.0],
+        }
+    }
+}
+
 impl Default for MusicConfig {
     fn default() -> Self {
         Self {
             soundfonts: default(),
+            bpm: 60.0,
+            beat: default(),
             ticks_per_beat: 4,
+            meter: default(),
+            metronome: default(),
+            sfx: default(),
+            skipped_ticks: default(),
+            patterns: default(),
             sections: default(),
         }
     }
@@ -183,6 +533,38 @@ mod serder {
         }
     }
 
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    pub(super) enum MidiImportSerde {
+        Path(String),
+        Full {
+            path: String,
+            track: Option<usize>,
+            channel: Option<u8>,
+        },
+    }
+
+    impl From<MidiImportSerde> for MidiImport {
+        fn from(value: MidiImportSerde) -> Self {
+            match value {
+                MidiImportSerde::Path(path) => Self {
+                    path,
+                    track: None,
+                    channel: None,
+                },
+                MidiImportSerde::Full {
+                    path,
+                    track,
+                    channel,
+                } => Self {
+                    path,
+                    track,
+                    channel,
+                },
+            }
+        }
+    }
+
     #[derive(Deserialize)]
     #[serde(untagged)]
     pub(super) enum NoteSerde {
@@ -190,11 +572,57 @@ mod serder {
         Eng(String),
     }
 
-    impl From<NoteSerde> for Note {
-        fn from(value: NoteSerde) -> Self {
+    impl TryFrom<String> for ChordName {
+        type Error = String;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            value
+                .parse()
+                .map_err(|()| format!("Unrecognized chord name: {value}"))
+        }
+    }
+
+    /// Gameplay notes come from outside the music config,
+    /// so they are checked when deserialized instead of by the validator.
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct GameNoteSerde {
+        note: NoteSpec,
+        program: Option<u8>,
+        velocity: Option<u32>,
+        duration: Option<Ticks>,
+    }
+
+    impl TryFrom<GameNoteSerde> for GameNote {
+        type Error = String;
+
+        fn try_from(value: GameNoteSerde) -> Result<Self, Self::Error> {
+            if let NoteSpec::Note(note) = value.note {
+                if !note.is_valid_midi() {
+                    return Err(format!("Note {note:?} is outside of the midi range"));
+                }
+            }
+            if let Some(velocity) = value.velocity {
+                if velocity > MIDI_MAX as u32 {
+                    return Err(format!("Velocity {velocity} is outside of the midi range"));
+                }
+            }
+            Ok(Self {
+                note: value.note,
+                program: value.program,
+                velocity: value.velocity,
+                duration: value.duration,
+            })
+        }
+    }
+
+    impl TryFrom<NoteSerde> for Note {
+        type Error = NoteParseError;
+
+        fn try_from(value: NoteSerde) -> Result<Self, Self::Error> {
             match value {
-                NoteSerde::Midi(midi) => Self::from_midi(midi),
-                NoteSerde::Eng(note) => note.parse().expect("Unrecognized note name"),
+                NoteSerde::Midi(midi) => Ok(Self::from_midi(midi)),
+                NoteSerde::Eng(note) => note.parse(),
             }
         }
     }
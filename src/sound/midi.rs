@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use geng::prelude::log;
+use midly::{MidiMessage, Smf, Timing, TrackEventKind};
+
+use super::config::*;
+
+pub type MidiResult<T> = Result<T, MidiError>;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum MidiError {
+    #[error("failed to parse midi file: {0}")]
+    Parse(String),
+    #[error("timecode-based midi timing is not supported, use metrical timing")]
+    UnsupportedTiming,
+    #[error("track {index} not found, the file has {count} tracks")]
+    TrackNotFound { index: usize, count: usize },
+}
+
+/// A note read from the midi file, positioned in midi ticks.
+struct MidiNote {
+    key: u8,
+    velocity: u32,
+    start: u64,
+    end: u64,
+}
+
+impl MusicConfig {
+    /// Returns the paths of the midi files imported by the sections.
+    pub fn midi_paths(&self) -> Vec<String> {
+        self.sections
+            .values()
+            .filter_map(|section| section.midi.as_ref())
+            .map(|import| import.path.clone())
+            .collect()
+    }
+
+    /// Replaces the events of the sections that import a midi file.
+    /// `load` reads the file at a path relative to the assets directory.
+    pub fn import_midi_sections<E: std::fmt::Display>(
+        &mut self,
+        mut load: impl FnMut(&str) -> Result<Vec<u8>, E>,
+    ) {
+        let ticks_per_beat = self.ticks_per_beat;
+        for (section_name, section) in &mut self.sections {
+            let Some(import) = &section.midi else {
+                continue;
+            };
+            let events = match load(&import.path) {
+                Ok(bytes) => {
+                    import_midi(&bytes, import, ticks_per_beat).map_err(|error| error.to_string())
+                }
+                Err(error) => Err(error.to_string()),
+            };
+            match events {
+                Ok(events) => section.events = events,
+                Err(error) => {
+                    // The section is left empty and will be skipped after validation
+                    log::error!(
+                        "Failed to import midi file {:?} for section {section_name:?}: {error}",
+                        import.path
+                    );
+                    section.events.clear();
+                }
+            }
+        }
+    }
+}
+
+/// Converts a standard midi file into section events, quantized to `ticks_per_beat`.
+///
+/// Notes starting on the same tick are merged into a chord.
+/// Since section events are played sequentially, a note that overlaps
+/// the start of the next one is cut short.
+pub fn import_midi(
+    bytes: &[u8],
+    import: &MidiImport,
+    ticks_per_beat: Ticks,
+) -> MidiResult<VecDeque<SectionEvent>> {
+    let smf = Smf::parse(bytes).map_err(|err| MidiError::Parse(err.to_string()))?;
+    let midi_ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks) => ticks.as_int() as u64,
+        Timing::Timecode(..) => return Err(MidiError::UnsupportedTiming),
+    };
+
+    let tracks: Vec<_> = match import.track {
+        Some(index) => {
+            let track = smf.tracks.get(index).ok_or(MidiError::TrackNotFound {
+                index,
+                count: smf.tracks.len(),
+            })?;
+            vec![track]
+        }
+        None => smf.tracks.iter().collect(),
+    };
+
+    // Collect the notes from all selected tracks
+    let mut notes = Vec::new();
+    for track in tracks {
+        let mut time = 0;
+        let mut playing: HashMap<u8, (u64, u32)> = HashMap::new();
+        for event in track {
+            time += event.delta.as_int() as u64;
+            let TrackEventKind::Midi { channel, message } = event.kind else {
+                continue;
+            };
+            if import
+                .channel
+                .map_or(false, |filter| filter != channel.as_int())
+            {
+                continue;
+            }
+
+            let (key, velocity) = match message {
+                MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                _ => continue,
+            };
+
+            // Finish the note if it was playing
+            if let Some((start, velocity)) = playing.remove(&key) {
+                notes.push(MidiNote {
+                    key,
+                    velocity,
+                    start,
+                    end: time,
+                });
+            }
+            // Note on with zero velocity means note off
+            if velocity > 0 {
+                playing.insert(key, (time, velocity as u32));
+            }
+        }
+
+        // Notes that were never turned off last until the end of the track
+        for (key, (start, velocity)) in playing {
+            notes.push(MidiNote {
+                key,
+                velocity,
+                start,
+                end: time,
+            });
+        }
+    }
+
+    // Quantize and group the notes by their starting tick
+    let quantize = |time: u64| -> Ticks {
+        let ticks = time as f64 * ticks_per_beat as f64 / midi_ticks_per_beat as f64;
+        ticks.round() as Ticks
+    };
+    let mut groups: BTreeMap<Ticks, Vec<(MidiNote, Ticks)>> = BTreeMap::new();
+    for note in notes {
+        let start = quantize(note.start);
+        let duration = quantize(note.end).saturating_sub(start).max(1);
+        groups.entry(start).or_default().push((note, duration));
+    }
+
+    // Convert into events
+    let mut events = VecDeque::new();
+    let mut current = 0;
+    let starts: Vec<Ticks> = groups.keys().copied().collect();
+    for (i, (start, group)) in groups.into_iter().enumerate() {
+        if start > current {
+            events.push_back(SectionEvent::Delay {
+                delay: start - current,
+            });
+        }
+
+        let duration = group.iter().map(|&(_, duration)| duration).max().unwrap();
+        let duration = match starts.get(i + 1) {
+            Some(&next) => duration.min(next - start),
+            None => duration,
+        };
+
+        let event = match &group[..] {
+            [(note, _)] => SectionEvent::Note(NoteOn {
+                note: NoteSpec::Note(Note::from_midi(note.key)),
+                velocity: Some(note.velocity),
+                duration: Some(duration),
+            }),
+            _ => SectionEvent::Chord(ChordOn {
+                chord: ChordSpec::Notes(
+                    group
+                        .iter()
+                        .map(|(note, _)| ChordNote::Full {
+                            note: NoteSpec::Note(Note::from_midi(note.key)),
+                            velocity: Some(note.velocity),
+                        })
+                        .collect(),
+                ),
+                velocity: None,
+                duration: Some(duration),
+            }),
+        };
+        events.push_back(event);
+        current = start + duration;
+    }
+
+    Ok(events)
+}
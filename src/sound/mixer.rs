@@ -0,0 +1,357 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use rodio::Source;
+use rustysynth::Synthesizer;
+
+/// Position on the mixer's timeline in samples.
+pub type SamplePos = u64;
+
+/// Index of a synthesizer added to the mixer.
+pub type InstrumentId = usize;
+
+/// A sound generated while it is being played.
+pub type StreamingSound = Box<dyn Iterator<Item = f32> + Send>;
+
+/// Number of samples mixed at once by the audio thread.
+const BLOCK_SIZE: usize = 512;
+
+/// Duration of the mixed audio kept for visualization, in seconds.
+const HISTORY_DURATION: f32 = 0.5;
+
+/// Mixes sounds scheduled at exact sample positions into a single stream.
+/// Synthesizers are rendered block by block as the stream plays,
+/// so the cost does not depend on the number or the length of the notes.
+/// The handle can be cloned and shared between the game and the audio thread.
+#[derive(Clone)]
+pub struct Mixer {
+    sample_rate: u32,
+    state: Arc<Mutex<MixerState>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MidiEvent {
+    NoteOn { channel: u8, key: u8, velocity: u32 },
+    NoteOff { channel: u8, key: u8 },
+    ProgramChange { channel: u8, program: u8 },
+}
+
+struct MixerState {
+    /// Number of samples mixed so far.
+    position: SamplePos,
+    voices: Vec<Voice>,
+    instruments: Vec<Instrument>,
+    /// Ring buffer of the last mixed samples.
+    history: Vec<f32>,
+    /// Index in `history` of the oldest sample.
+    history_start: usize,
+    /// Scratch buffers for rendering the synthesizers.
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+struct Voice {
+    /// Position at which the sound should start playing.
+    start: SamplePos,
+    sound: StreamingSound,
+}
+
+struct Instrument {
+    synthesizer: Synthesizer,
+    gain: f32,
+    /// Events waiting to be sent to the synthesizer, sorted by position.
+    events: VecDeque<(SamplePos, MidiEvent)>,
+}
+
+/// Streams the output of the mixer. Never runs out of samples.
+pub struct MixerSource {
+    mixer: Mixer,
+    block: Vec<f32>,
+    block_pos: usize,
+}
+
+/// Keeps the audio device playing the mixer's output while alive.
+pub struct MixerOutput {
+    #[cfg(not(target_arch = "wasm32"))]
+    _stream: rodio::OutputStream,
+    /// rodio has no audio device on the web, so the output is played
+    /// through geng's audio context instead, see [`MixerOutput::update`].
+    #[cfg(target_arch = "wasm32")]
+    geng: geng::Geng,
+    #[cfg(target_arch = "wasm32")]
+    mixer: Mixer,
+    /// Number of samples that should have been played by now.
+    #[cfg(target_arch = "wasm32")]
+    played: f64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MixerError {
+    #[error("failed to open the audio device: {0}")]
+    Stream(#[from] rodio::StreamError),
+    #[error("failed to play the audio: {0}")]
+    Play(#[from] rodio::PlayError),
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        let history_size = (sample_rate as f32 * HISTORY_DURATION) as usize;
+        Self {
+            sample_rate,
+            state: Arc::new(Mutex::new(MixerState {
+                position: 0,
+                voices: Vec::new(),
+                instruments: Vec::new(),
+                history: vec![0.0; history_size],
+                history_start: 0,
+                left: Vec::with_capacity(BLOCK_SIZE),
+                right: Vec::with_capacity(BLOCK_SIZE),
+            })),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of samples mixed so far.
+    pub fn position(&self) -> SamplePos {
+        self.state.lock().unwrap().position
+    }
+
+    /// Returns the last mixed samples, oldest first.
+    pub fn history(&self) -> Vec<f32> {
+        let state = self.state.lock().unwrap();
+        let (old, new) = state.history.split_at(state.history_start);
+        new.iter().chain(old).copied().collect()
+    }
+
+    /// Adds the synthesizer to be rendered by the mixer.
+    pub fn add_instrument(&self, synthesizer: Synthesizer) -> InstrumentId {
+        let mut state = self.state.lock().unwrap();
+        state.instruments.push(Instrument {
+            synthesizer,
+            gain: 1.0,
+            events: VecDeque::new(),
+        });
+        state.instruments.len() - 1
+    }
+
+    pub fn set_gain(&self, instrument: InstrumentId, gain: f32) {
+        let mut state = self.state.lock().unwrap();
+        let instrument = state
+            .instruments
+            .get_mut(instrument)
+            .expect("Instrument not found");
+        instrument.gain = gain;
+    }
+
+    /// Schedules the event to be sent to the instrument at the `position`.
+    /// Events scheduled in the past are sent as soon as possible.
+    pub fn schedule_event(&self, instrument: InstrumentId, event: MidiEvent, position: SamplePos) {
+        let mut state = self.state.lock().unwrap();
+        let events = &mut state
+            .instruments
+            .get_mut(instrument)
+            .expect("Instrument not found")
+            .events;
+        // Events at the same position keep the order they were scheduled in
+        let index = events.partition_point(|&(pos, _)| pos <= position);
+        events.insert(index, (position, event));
+    }
+
+    /// Schedules the sound to start playing at the `start` position.
+    /// Sounds scheduled in the past start as soon as possible.
+    pub fn schedule(&self, sound: impl Iterator<Item = f32> + Send + 'static, start: SamplePos) {
+        self.state.lock().unwrap().voices.push(Voice {
+            start,
+            sound: Box::new(sound),
+        });
+    }
+
+    /// Mixes the next `output.len()` samples into `output`.
+    pub fn render(&self, output: &mut [f32]) {
+        output.fill(0.0);
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let position = state.position;
+        let end = position + output.len() as SamplePos;
+
+        for instrument in &mut state.instruments {
+            instrument.render(position, output, &mut state.left, &mut state.right);
+        }
+
+        state.voices.retain_mut(|voice| {
+            if voice.start >= end {
+                // Not yet
+                return true;
+            }
+            let skip = voice.start.saturating_sub(position) as usize;
+            for sample in &mut output[skip..] {
+                match voice.sound.next() {
+                    Some(value) => *sample += value,
+                    None => return false,
+                }
+            }
+            true
+        });
+
+        // Remember for visualization
+        let size = state.history.len();
+        if size > 0 {
+            for &sample in output.iter() {
+                state.history[state.history_start] = sample;
+                state.history_start = (state.history_start + 1) % size;
+            }
+        }
+
+        state.position = end;
+    }
+
+    /// Creates a source that streams the mixer's output.
+    pub fn source(&self) -> MixerSource {
+        MixerSource {
+            mixer: self.clone(),
+            block: vec![0.0; BLOCK_SIZE],
+            block_pos: BLOCK_SIZE,
+        }
+    }
+
+    /// Starts playing the mixer on the default audio device.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn play(&self, _geng: &geng::Geng) -> Result<MixerOutput, MixerError> {
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        handle.play_raw(self.source())?;
+        Ok(MixerOutput { _stream: stream })
+    }
+
+    /// Starts playing the mixer through geng's audio context.
+    #[cfg(target_arch = "wasm32")]
+    pub fn play(&self, geng: &geng::Geng) -> Result<MixerOutput, MixerError> {
+        Ok(MixerOutput {
+            geng: geng.clone(),
+            mixer: self.clone(),
+            played: self.position() as f64,
+        })
+    }
+}
+
+impl MixerOutput {
+    /// Should be called every frame.
+    /// On the web, renders the mixer's output for the frame
+    /// and plays it through geng's audio context.
+    /// Elsewhere the audio device pulls the samples by itself.
+    pub fn update(&mut self, delta_time: f32) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let sample_rate = self.mixer.sample_rate();
+            self.played += delta_time as f64 * sample_rate as f64;
+            let len = (self.played as SamplePos).saturating_sub(self.mixer.position()) as usize;
+            if len == 0 {
+                return;
+            }
+            let mut data = vec![0.0; len];
+            self.mixer.render(&mut data);
+            self.geng.audio().from_raw(data, sample_rate as f32).play();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = delta_time;
+    }
+}
+
+impl Instrument {
+    /// Renders the synthesizer into `output` starting at the `start` position,
+    /// sending the events exactly at their positions.
+    fn render(
+        &mut self,
+        start: SamplePos,
+        output: &mut [f32],
+        left: &mut Vec<f32>,
+        right: &mut Vec<f32>,
+    ) {
+        let end = start + output.len() as SamplePos;
+        let mut position = start;
+        while position < end {
+            // Send the events that are due
+            while let Some(&(event_pos, event)) = self.events.front() {
+                if event_pos > position {
+                    break;
+                }
+                self.events.pop_front();
+                self.send(event);
+            }
+
+            // Render until the next event
+            let next = self
+                .events
+                .front()
+                .map_or(end, |&(event_pos, _)| event_pos.min(end));
+            let from = (position - start) as usize;
+            let len = (next - position) as usize;
+            left.resize(len, 0.0);
+            right.resize(len, 0.0);
+            self.synthesizer.render(left, right);
+
+            // TODO: stereo
+            for (sample, value) in output[from..from + len].iter_mut().zip(left.iter()) {
+                *sample += value * self.gain;
+            }
+            position = next;
+        }
+    }
+
+    fn send(&mut self, event: MidiEvent) {
+        match event {
+            MidiEvent::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => {
+                self.synthesizer
+                    .note_on(channel.into(), key.into(), velocity as i32);
+            }
+            MidiEvent::NoteOff { channel, key } => {
+                self.synthesizer.note_off(channel.into(), key.into());
+            }
+            MidiEvent::ProgramChange { channel, program } => {
+                self.synthesizer
+                    .process_midi_message(channel.into(), 0xC0, program.into(), 0);
+            }
+        }
+    }
+}
+
+impl Iterator for MixerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block_pos >= self.block.len() {
+            self.mixer.render(&mut self.block);
+            self.block_pos = 0;
+        }
+        let sample = self.block[self.block_pos];
+        self.block_pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for MixerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
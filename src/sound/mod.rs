@@ -1,12 +1,21 @@
 mod beat_controller;
+mod calibration;
 mod config;
+mod midi;
+mod mixer;
 mod music_controller;
-mod sound_queue;
+mod sfx;
 mod source;
 pub mod synthesize;
+mod validate;
 
 pub use beat_controller::*;
+pub use calibration::*;
 pub use config::*;
+pub use midi::*;
+pub use mixer::*;
 pub use music_controller::*;
 pub use rustysynth::Synthesizer;
-pub use sound_queue::*;
+pub use sfx::*;
+pub use synthesize::Synthesizers;
+pub use validate::*;
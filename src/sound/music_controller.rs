@@ -1,48 +1,107 @@
 use std::collections::HashMap;
 
-use geng::prelude::{thread_rng, IteratorRandom};
+use geng::prelude::{log, thread_rng, IteratorRandom};
+use rodio::Source;
 use rustysynth::Synthesizer;
 
 use super::{
     config::*,
-    sound_queue::SoundQueue,
-    source::{IntoRawSource, RawSource},
-    synthesize,
+    mixer::{InstrumentId, MidiEvent, Mixer, SamplePos},
+    source,
+    synthesize::{self, Synthesizers},
 };
 
+/// Delay between scheduling a sound and playing it,
+/// gives the audio thread time to pick up the scheduled sounds.
+pub const SCHEDULE_LATENCY: f32 = 0.05;
+/// Maximum difference between the music time and the mixer's position
+/// before the music time gets resynchronized.
+const MAX_DRIFT: f32 = 0.1;
+/// Time right before the next tick into which `SkipPolicy::Compress` squeezes the skipped ticks.
+/// Shorter than `SCHEDULE_LATENCY`, so that they are still scheduled in the future.
+const COMPRESS_WINDOW: f32 = SCHEDULE_LATENCY / 2.0;
+
 pub struct MusicController {
     config: MusicConfig,
     bpm: f32,
     tick_t: f32,
     tick: Ticks,
-    synthesizers: HashMap<SectionName, Synthesizer>,
+    instruments: HashMap<SectionName, InstrumentId>,
+    metronome_instrument: Option<InstrumentId>,
+    gameplay_instruments: HashMap<SFName, InstrumentId>,
+    /// Notes requested by the gameplay to be played on the next tick.
+    queued_notes: Vec<GameNote>,
     current_section: Option<(SectionName, SectionConfig)>,
-    sounds_queue: SoundQueue,
-    buffer: RawSource,
+    mixer: Mixer,
+    /// Current music time in seconds.
+    time: f32,
+    /// Time of the tick currently being processed relative to `time`.
+    tick_delay: f32,
+    /// Whether the sounds of the current tick should be discarded.
+    muted: bool,
+    /// Number of skipped ticks yet to be played, see `SkipPolicy::Stretch`.
+    stretched_ticks: Ticks,
 }
 
 impl MusicController {
-    pub fn new(
-        config: MusicConfig,
-        bpm: f32,
-        synthesizers: HashMap<SectionName, Synthesizer>,
-    ) -> Self {
+    /// Creates the controller, moving the synthesizers into the mixer.
+    pub fn new(config: MusicConfig, bpm: f32, synthesizers: Synthesizers, mixer: Mixer) -> Self {
+        let add_all = |synthesizers: HashMap<String, Synthesizer>| -> HashMap<_, _> {
+            synthesizers
+                .into_iter()
+                .map(|(name, synthesizer)| (name, mixer.add_instrument(synthesizer)))
+                .collect()
+        };
+        let instruments = add_all(synthesizers.sections);
+        let gameplay_instruments = add_all(synthesizers.gameplay);
+        let metronome_instrument = synthesizers.metronome.map(|synthesizer| {
+            let instrument = mixer.add_instrument(synthesizer);
+            mixer.set_gain(instrument, config.metronome.volume);
+            instrument
+        });
+
         let mut controller = Self {
             config,
             bpm: 1.0,
             tick_t: 1.0,
             tick: 0,
-            synthesizers,
+            instruments,
+            metronome_instrument,
+            gameplay_instruments,
+            queued_notes: Vec::new(),
             current_section: None,
-            sounds_queue: SoundQueue::new(),
-            buffer: RawSource::new(44100, vec![]),
+            mixer,
+            time: 0.0,
+            tick_delay: 0.0,
+            muted: false,
+            stretched_ticks: 0,
         };
         controller.set_bpm(bpm);
         controller
     }
 
-    pub fn get_buffer(&self) -> &RawSource {
-        &self.buffer
+    pub fn get_mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    pub fn is_metronome_enabled(&self) -> bool {
+        self.config.metronome.enabled
+    }
+
+    pub fn set_metronome_enabled(&mut self, enabled: bool) {
+        self.config.metronome.enabled = enabled;
+    }
+
+    /// Queues the note to be played on the next tick
+    /// in the key and soundfont of the current section.
+    pub fn queue_note(&mut self, note: GameNote) {
+        self.queued_notes.push(note);
+    }
+
+    /// Returns the mixer position for a sound played `delay` seconds after the current music time.
+    pub fn schedule_position(&self, delay: f32) -> SamplePos {
+        let time = self.time + delay + SCHEDULE_LATENCY;
+        (time.max(0.0) * self.mixer.sample_rate() as f32) as SamplePos
     }
 
     pub fn set_bpm(&mut self, bpm: f32) {
@@ -50,28 +109,78 @@ impl MusicController {
         self.tick_t = 60.0 / (bpm * self.config.ticks_per_beat as f32);
     }
 
-    pub fn update(&mut self, delta_time: f32) -> Vec<RawSource> {
-        // Audio buffer
-        for _ in 0..(rodio::Source::sample_rate(&self.buffer) as f32 * delta_time) as u32 {
-            if self.buffer.next().is_none() {
+    /// Advances the music time.
+    /// Should be called after all the ticks of the frame have been processed.
+    /// Renders and discards the mixer's output up to the music time after `delta_time`,
+    /// so that the scheduled sounds do not pile up when no audio device plays the mixer.
+    pub fn drain_mixer(&mut self, delta_time: f32) {
+        let target =
+            ((self.time + delta_time).max(0.0) * self.mixer.sample_rate() as f32) as SamplePos;
+        let mut block = [0.0; 512];
+        loop {
+            let position = self.mixer.position();
+            if position >= target {
                 break;
             }
+            let len = ((target - position) as usize).min(block.len());
+            self.mixer.render(&mut block[..len]);
         }
+    }
 
-        // Sound queue
-        let mut mix = RawSource::new(rodio::Source::sample_rate(&self.buffer), vec![]);
-        std::mem::swap(&mut self.buffer, &mut mix);
-        let mut sounds = Vec::new();
-        for sound in self.sounds_queue.update(delta_time) {
-            mix = rodio::Source::mix(sound.clone(), mix).into_raw_source();
-            sounds.push(sound);
+    pub fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+
+        // Keep in sync with the audio thread
+        let mixer_time = self.mixer.position() as f32 / self.mixer.sample_rate() as f32;
+        if (self.time - mixer_time).abs() > MAX_DRIFT {
+            log::debug!(
+                "Music time drifted from the mixer by {}s",
+                self.time - mixer_time
+            );
+            self.time = mixer_time;
         }
-        self.buffer = mix;
-        sounds
     }
 
-    pub fn tick(&mut self) {
+    /// Processes the next tick.
+    /// `delay` is the time of the tick relative to the current music time,
+    /// which allows placing the ticks precisely within a frame.
+    pub fn tick(&mut self, delay: f32) {
+        self.process_tick(delay);
+
+        if self.stretched_ticks > 0 {
+            // Catch up by one skipped tick in between the regular ones
+            self.stretched_ticks -= 1;
+            self.process_tick(delay + self.tick_t / 2.0);
+        }
+    }
+
+    /// Skips over the ticks, handling their events according to the `SkipPolicy`.
+    pub fn skip_ticks(&mut self, ticks: Ticks) {
+        match self.config.skipped_ticks {
+            SkipPolicy::Drop => {
+                self.muted = true;
+                for _ in 0..ticks {
+                    self.process_tick(0.0);
+                }
+                self.muted = false;
+            }
+            SkipPolicy::Compress => {
+                // The next tick follows right away, so end the skipped ones before it
+                // to keep the events in order
+                let window = COMPRESS_WINDOW.min(self.tick_t);
+                for i in 0..ticks {
+                    self.process_tick(-window * (ticks - i) as f32 / ticks as f32);
+                }
+            }
+            SkipPolicy::Stretch => {
+                self.stretched_ticks += ticks;
+            }
+        }
+    }
+
+    fn process_tick(&mut self, delay: f32) {
         self.tick += 1;
+        self.tick_delay = delay;
 
         let is_beat = self.tick % self.config.ticks_per_beat == 0;
         if is_beat {
@@ -79,14 +188,79 @@ impl MusicController {
         }
 
         self.section_tick(is_beat);
+        self.play_queued_notes();
+    }
+
+    fn play_queued_notes(&mut self) {
+        if self.queued_notes.is_empty() {
+            return;
+        }
+        let Some((_, section)) = &self.current_section else {
+            // No music to fit the notes into
+            self.queued_notes.clear();
+            return;
+        };
+        let instrument = *self
+            .gameplay_instruments
+            .get(&section.soundfont.name)
+            .expect("Failed to get the gameplay instrument");
+
+        let notes: Vec<_> = self
+            .queued_notes
+            .drain(..)
+            .map(|note| {
+                let program = note.program.or(section.soundfont.program).unwrap_or(0);
+                let notes = [(
+                    note.note.to_note(section.key),
+                    note.velocity.unwrap_or(section.default_velocity),
+                )];
+                let duration = note.duration.unwrap_or(section.default_duration);
+                (program, notes, duration)
+            })
+            .collect();
+        for (program, notes, duration) in notes {
+            if self.muted {
+                break;
+            }
+            let position = self.schedule_position(self.tick_delay);
+            let event = MidiEvent::ProgramChange {
+                channel: 0,
+                program,
+            };
+            self.mixer.schedule_event(instrument, event, position);
+            self.play_notes(instrument, &notes, duration);
+        }
+    }
+
+    /// Schedules the sound to play at the time of the current tick.
+    fn play(&mut self, sound: impl Iterator<Item = f32> + Send + 'static) {
+        if self.muted {
+            return;
+        }
+
+        let position = self.schedule_position(self.tick_delay);
+        self.mixer.schedule(sound, position);
+    }
+
+    /// Schedules the notes to be played by the instrument
+    /// at the time of the current tick for `duration` ticks.
+    fn play_notes(&mut self, instrument: InstrumentId, notes: &[(Note, u32)], duration: Ticks) {
+        if self.muted {
+            return;
+        }
+
+        let position = self.schedule_position(self.tick_delay);
+        let duration = duration as f32 * self.tick_t;
+        synthesize::schedule_notes(&self.mixer, instrument, notes, position, duration);
     }
 
     fn section_tick(&mut self, immediate_next_section: bool) {
+        let mut notes = Vec::new();
         if let Some((section_name, section)) = &mut self.current_section {
-            let synthesizer = self
-                .synthesizers
-                .get_mut(section_name)
-                .expect("Failed to get the section's synthesizer");
+            let instrument = *self
+                .instruments
+                .get(section_name)
+                .expect("Failed to get the section's instrument");
 
             // Next section event
             while let Some(event) = section.events.pop_front() {
@@ -108,14 +282,23 @@ impl MusicController {
                     }
                     SectionEvent::Note(note) => {
                         let duration = note.duration.unwrap_or(section.default_duration);
-                        self.sounds_queue
-                            .play_immediately(synthesize::synthesize_note(
-                                note.note.to_note(section.key),
-                                note.velocity.unwrap_or(section.default_velocity),
-                                duration,
-                                self.tick_t,
-                                synthesizer,
-                            ));
+                        let velocity = note.velocity.unwrap_or(section.default_velocity);
+                        notes.push((
+                            instrument,
+                            vec![(note.note.to_note(section.key), velocity)],
+                            duration,
+                        ));
+                        section
+                            .events
+                            .push_front(SectionEvent::Delay { delay: duration });
+                    }
+                    SectionEvent::Chord(chord) => {
+                        let duration = chord.duration.unwrap_or(section.default_duration);
+                        let chord_notes = chord.chord.to_notes(
+                            section.key,
+                            chord.velocity.unwrap_or(section.default_velocity),
+                        );
+                        notes.push((instrument, chord_notes, duration));
                         section
                             .events
                             .push_front(SectionEvent::Delay { delay: duration });
@@ -127,6 +310,30 @@ impl MusicController {
                         SectionEventTagged::ChangeDefaultDuration { value } => {
                             section.default_duration = value;
                         }
+                        SectionEventTagged::Repeat { count, events } => {
+                            // Expand one iteration at a time
+                            if count > 0 {
+                                section.events.push_front(SectionEvent::Tagged(
+                                    SectionEventTagged::Repeat {
+                                        count: count - 1,
+                                        events: events.clone(),
+                                    },
+                                ));
+                                for event in events.into_iter().rev() {
+                                    section.events.push_front(event);
+                                }
+                            }
+                        }
+                        SectionEventTagged::Pattern { name } => {
+                            match self.config.patterns.get(&name) {
+                                Some(events) => {
+                                    for event in events.iter().rev() {
+                                        section.events.push_front(event.clone());
+                                    }
+                                }
+                                None => log::warn!("Unknown pattern {name:?}"),
+                            }
+                        }
                     },
                 }
             }
@@ -140,27 +347,64 @@ impl MusicController {
                 }
             }
         }
+
+        for (instrument, notes, duration) in notes {
+            self.play_notes(instrument, &notes, duration);
+        }
     }
 
     fn beat(&mut self) {
-        // let beat = self.tick / self.config.ticks_per_beat;
-
-        // let sound = if beat % 2 == 0 {
-        //     // Major beat
-        //     source::Beat::default().into_raw_source()
-        // } else {
-        //     // Minor beat
-        //     source::Beat::new(150.0, 0.2).into_raw_source()
-        // };
-
-        // self.sounds_queue.play_immediately(sound);
+        if self.config.metronome.enabled {
+            self.metronome_click();
+        }
 
         if self.current_section.is_none() {
             self.next_section();
         }
     }
 
+    /// Plays the metronome sound for the current beat.
+    /// Accented beats get a different sound and are played louder.
+    fn metronome_click(&mut self) {
+        let beat_in_bar = self.tick / self.config.ticks_per_beat % self.config.meter.beats_per_bar;
+        let accent = self.config.meter.accent(beat_in_bar);
+        let major = accent > 0.0;
+        let loudness = 0.5 + 0.5 * accent.clamp(0.0, 1.0);
+
+        match (
+            self.config.metronome.sample.clone(),
+            self.metronome_instrument,
+        ) {
+            (Some(sample), Some(instrument)) => {
+                // The volume is applied by the instrument's gain
+                let note = if major { sample.accent } else { sample.note };
+                let velocity = (sample.velocity as f32 * loudness).round() as u32;
+                self.play_notes(instrument, &[(note, velocity)], 1);
+            }
+            _ => {
+                let beat = if major {
+                    // Major beat
+                    source::Beat::default()
+                } else {
+                    // Minor beat
+                    source::Beat::new(150.0, 0.2)
+                };
+                let sound = beat
+                    .with_sample_rate(self.mixer.sample_rate())
+                    .amplify(self.config.metronome.volume * loudness);
+                self.play(sound);
+            }
+        }
+    }
+
+    /// Whether the current tick is the first tick of a bar.
+    fn is_downbeat(&self) -> bool {
+        self.tick % (self.config.ticks_per_beat * self.config.meter.beats_per_bar) == 0
+    }
+
     fn next_section(&mut self) {
+        let is_downbeat = self.is_downbeat();
+
         // Get the next section
         if let Some((section_name, section)) = self
             .config
@@ -170,6 +414,7 @@ impl MusicController {
                 // Filter BPM range
                 self.bpm >= section.bpm_range[0] as f32 && self.bpm <= section.bpm_range[1] as f32
             })
+            .filter(|(_, section)| !section.on_downbeat || is_downbeat)
             .choose(&mut thread_rng())
         {
             self.current_section = Some((section_name.to_owned(), section.clone()));
@@ -0,0 +1,162 @@
+use geng::prelude::*;
+use rodio::Source;
+use rustysynth::Synthesizer;
+
+use super::{
+    config::*,
+    mixer::{InstrumentId, Mixer, SamplePos},
+    source::Beat,
+    synthesize,
+};
+
+/// Sound effects of the gameplay events.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SfxConfig {
+    /// Volume of the sound effects in range 0..1, separate from the music.
+    pub volume: f32,
+    /// Delay the sound effects until the next music tick,
+    /// so that they land on the grid of the music.
+    pub quantize: bool,
+    /// Soundfont used by the `SfxSound::Note` sounds.
+    pub soundfont: Option<SFConfig>,
+    pub sounds: HashMap<SfxEvent, SfxSound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum SfxEvent {
+    /// A unit takes damage.
+    Hit,
+    /// A unit dies.
+    Death,
+    ProjectileLaunch,
+    /// The player misses the beat.
+    Miss,
+    /// All enemies of a wave are killed.
+    WaveCleared,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum SfxSound {
+    /// A note from the sfx soundfont.
+    Note {
+        note: Note,
+        velocity: u32,
+        /// Duration in seconds.
+        duration: f32,
+    },
+    /// A generated click.
+    Click {
+        frequency: f32,
+        /// Duration in seconds.
+        duration: f32,
+    },
+}
+
+/// Plays the sound effects on the music's mixer.
+pub struct SfxController {
+    config: SfxConfig,
+    instrument: Option<InstrumentId>,
+    mixer: Mixer,
+    /// Events waiting for the next music tick.
+    queued: Vec<SfxEvent>,
+}
+
+impl SfxController {
+    /// Creates the controller, moving the synthesizer into the mixer.
+    pub fn new(config: SfxConfig, synthesizer: Option<Synthesizer>, mixer: Mixer) -> Self {
+        let instrument = synthesizer.map(|synthesizer| {
+            let instrument = mixer.add_instrument(synthesizer);
+            mixer.set_gain(instrument, config.volume);
+            instrument
+        });
+        Self {
+            config,
+            instrument,
+            mixer,
+            queued: Vec::new(),
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.config.volume = volume;
+        if let Some(instrument) = self.instrument {
+            self.mixer.set_gain(instrument, volume);
+        }
+    }
+
+    /// Plays the sound of the event at the `position`,
+    /// or at the next tick if the effects are quantized.
+    pub fn play(&mut self, event: SfxEvent, position: SamplePos) {
+        if self.config.quantize {
+            self.queued.push(event);
+        } else {
+            self.play_at(event, position);
+        }
+    }
+
+    /// Plays the queued sounds at the `position` of the music tick.
+    pub fn tick(&mut self, position: SamplePos) {
+        for event in std::mem::take(&mut self.queued) {
+            self.play_at(event, position);
+        }
+    }
+
+    fn play_at(&mut self, event: SfxEvent, position: SamplePos) {
+        let Some(sound) = self.config.sounds.get(&event) else {
+            return;
+        };
+        match *sound {
+            SfxSound::Note {
+                note,
+                velocity,
+                duration,
+            } => {
+                let Some(instrument) = self.instrument else {
+                    log::warn!("No soundfont for the sound effect of {event:?}");
+                    return;
+                };
+                synthesize::schedule_notes(
+                    &self.mixer,
+                    instrument,
+                    &[(note, velocity)],
+                    position,
+                    duration,
+                );
+            }
+            SfxSound::Click {
+                frequency,
+                duration,
+            } => {
+                let sound = Beat::new(frequency, duration)
+                    .with_sample_rate(self.mixer.sample_rate())
+                    .amplify(self.config.volume);
+                self.mixer.schedule(sound, position);
+            }
+        }
+    }
+}
+
+impl Default for SfxConfig {
+    fn default() -> Self {
+        let click = |frequency, duration| SfxSound::Click {
+            frequency,
+            duration,
+        };
+        Self {
+            volume: 0.5,
+            quantize: true,
+            soundfont: None,
+            sounds: [
+                (SfxEvent::Hit, click(300.0, 0.1)),
+                (SfxEvent::Death, click(80.0, 0.4)),
+                (SfxEvent::ProjectileLaunch, click(500.0, 0.05)),
+                (SfxEvent::Miss, click(60.0, 0.2)),
+                (SfxEvent::WaveCleared, click(880.0, 0.5)),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use super::*;
 pub struct Beat {
     frequency: f32,
     duration: f32,
+    sample_rate: u32,
     num_sample: usize,
 }
 
@@ -12,9 +13,17 @@ impl Beat {
         Self {
             frequency,
             duration,
+            sample_rate: 48000,
             num_sample: 0,
         }
     }
+
+    pub fn with_sample_rate(self, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            ..self
+        }
+    }
 }
 
 impl Default for Beat {
@@ -28,7 +37,7 @@ impl Iterator for Beat {
 
     fn next(&mut self) -> Option<f32> {
         self.num_sample = self.num_sample.wrapping_add(1);
-        let t = self.num_sample as f32 / 48000.0 * 1.0;
+        let t = self.num_sample as f32 / self.sample_rate as f32;
         if t > self.duration {
             return None;
         }
@@ -53,7 +62,7 @@ impl Source for Beat {
 
     #[inline]
     fn sample_rate(&self) -> u32 {
-        48000
+        self.sample_rate
     }
 
     #[inline]
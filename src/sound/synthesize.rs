@@ -1,43 +1,104 @@
-use rodio::Source;
-use rustysynth::Synthesizer;
+use std::{collections::HashMap, sync::Arc};
+
+use rustysynth::{SoundFont, Synthesizer};
 
 use super::{
-    config::{Note, Ticks},
-    source::RawSource,
+    config::{MusicConfig, Note, SFConfig, SFName, SectionName},
+    mixer::{InstrumentId, MidiEvent, Mixer, SamplePos},
 };
 
-pub fn synthesize_note(
-    note: Note,
-    velocity: u32,
-    duration: Ticks,
-    tick_t: f32,
-    synthesizer: &mut Synthesizer,
-) -> RawSource {
-    // Set the note
-    let note = note.to_midi().into();
-    synthesizer.note_on(0, note, velocity as i32);
-
-    // Synthesize the note playing
-    let note_on = synthesize_ticks(duration, tick_t, synthesizer);
+pub const SAMPLE_RATE: i32 = 44000;
 
-    // Turn off the note
-    synthesizer.note_off(0, note);
+/// Synthesizers used by the music.
+pub struct Synthesizers {
+    /// Synthesizer of every section.
+    pub sections: HashMap<SectionName, Synthesizer>,
+    /// Synthesizer of the metronome, if it plays a sample from a soundfont.
+    pub metronome: Option<Synthesizer>,
+    /// Synthesizer of the sound effects, if they have a soundfont.
+    pub sfx: Option<Synthesizer>,
+    /// Synthesizer for the notes played by the gameplay for every soundfont.
+    pub gameplay: HashMap<SFName, Synthesizer>,
+}
 
-    // Render the note turning off
-    let note_off = synthesize_ticks(1, tick_t, synthesizer);
+pub type SynthesizeResult<T> = Result<T, SynthesizeError>;
 
-    RawSource::new(note_on.sample_rate(), note_on.chain(note_off).collect())
+#[derive(thiserror::Error, Debug)]
+pub enum SynthesizeError {
+    #[error("unknown soundfont {0:?}")]
+    UnknownSoundfont(SFName),
+    #[error("failed to create a synthesizer: {0}")]
+    Synthesizer(#[from] rustysynth::SynthesizerError),
 }
 
-fn synthesize_ticks(ticks: Ticks, tick_t: f32, synthesizer: &mut Synthesizer) -> RawSource {
-    // The output buffer
-    let sample_count = (ticks as f32 * tick_t * synthesizer.get_sample_rate() as f32) as usize;
-    let mut left: Vec<f32> = vec![0_f32; sample_count];
-    let mut right: Vec<f32> = vec![0_f32; sample_count];
+/// Creates a synthesizer for every section using the section's soundfont,
+/// and ones for the metronome sample, the sound effects and the gameplay notes.
+pub fn create_synthesizers(
+    config: &MusicConfig,
+    soundfonts: &HashMap<SFName, Arc<SoundFont>>,
+) -> SynthesizeResult<Synthesizers> {
+    let settings = rustysynth::SynthesizerSettings::new(SAMPLE_RATE);
+    let create = |sf: &SFConfig| -> SynthesizeResult<Synthesizer> {
+        let soundfont = soundfonts
+            .get(&sf.name)
+            .ok_or_else(|| SynthesizeError::UnknownSoundfont(sf.name.clone()))?;
+        let mut synthesizer = Synthesizer::new(soundfont, &settings)?;
+        if let Some(program) = sf.program {
+            // Program change
+            synthesizer.process_midi_message(0, 0xC0, program.into(), 0);
+        }
+        Ok(synthesizer)
+    };
 
-    // Render the waveform
-    synthesizer.render(&mut left[..], &mut right[..]);
+    let mut sections = HashMap::new();
+    for (section_name, section) in &config.sections {
+        sections.insert(section_name.to_owned(), create(&section.soundfont)?);
+    }
+    let metronome = config
+        .metronome
+        .sample
+        .as_ref()
+        .map(|sample| create(&sample.soundfont))
+        .transpose()?;
+    let sfx = config.sfx.soundfont.as_ref().map(create).transpose()?;
+    let mut gameplay = HashMap::new();
+    for name in config.soundfonts.keys() {
+        let sf = SFConfig {
+            name: name.to_owned(),
+            program: None,
+        };
+        gameplay.insert(name.to_owned(), create(&sf)?);
+    }
+    Ok(Synthesizers {
+        sections,
+        metronome,
+        sfx,
+        gameplay,
+    })
+}
 
-    // TODO: stereo
-    RawSource::new(synthesizer.get_sample_rate() as u32, left)
+/// Schedules the notes to be played by the instrument
+/// from the `start` position for `duration` seconds.
+pub fn schedule_notes(
+    mixer: &Mixer,
+    instrument: InstrumentId,
+    notes: &[(Note, u32)],
+    start: SamplePos,
+    duration: f32,
+) {
+    let end = start + (duration * mixer.sample_rate() as f32) as SamplePos;
+    for &(note, velocity) in notes {
+        let key = note.to_midi();
+        let channel = 0;
+        mixer.schedule_event(
+            instrument,
+            MidiEvent::NoteOn {
+                channel,
+                key,
+                velocity,
+            },
+            start,
+        );
+        mixer.schedule_event(instrument, MidiEvent::NoteOff { channel, key }, end);
+    }
 }
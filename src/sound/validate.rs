@@ -0,0 +1,540 @@
+use geng::prelude::log;
+
+use super::{
+    beat_controller::{BpmSmoothing, TempoMode},
+    config::*,
+    sfx::SfxSound,
+};
+
+/// Maximum number of iterations of a repeat that takes no time,
+/// all of which are expanded within a single tick.
+const MAX_INSTANT_REPEATS: Ticks = 1000;
+
+/// A problem found in the music config.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("{path}: {kind}")]
+pub struct ConfigError {
+    /// Location of the problem in the config, e.g. `sections.slow.events[2]`.
+    pub path: String,
+    /// The section that the problem belongs to, if any.
+    pub section: Option<SectionName>,
+    pub kind: ConfigErrorKind,
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ConfigErrorKind {
+    #[error("ticks per beat must be positive")]
+    ZeroTicksPerBeat,
+    #[error("beats per bar must be positive")]
+    ZeroBeatsPerBar,
+    #[error("unknown soundfont {0:?}")]
+    UnknownSoundfont(SFName),
+    #[error("invalid bpm range [{0}, {1}]")]
+    InvalidBpmRange(Ticks, Ticks),
+    #[error("bpm {0} is outside of the allowed range [{1}, {2}]")]
+    BpmOutOfRange(f32, Ticks, Ticks),
+    #[error("bpm {0} must be positive")]
+    NonPositiveBpm(f32),
+    #[error("max bpm change {0} must not be negative")]
+    NegativeBpmChange(f32),
+    #[error("smoothing factor {0} is outside of the range [0, 1]")]
+    SmoothingFactorOutOfRange(f32),
+    #[error("bpm range [{0}, {1}] is not covered by any section")]
+    BpmNotCovered(Ticks, Ticks),
+    #[error("bpm range overlaps with section {0:?}")]
+    BpmOverlap(SectionName),
+    #[error("section has no events")]
+    EmptySection,
+    #[error("note {0:?} is outside of the midi range")]
+    NoteOutOfRange(Note),
+    #[error("shifting {key} by {delta} semitones leaves the midi range")]
+    ShiftOutOfRange { key: Note, delta: i8 },
+    #[error("velocity {0} is outside of the midi range")]
+    VelocityOutOfRange(u32),
+    #[error("unknown pattern {0:?}")]
+    UnknownPattern(PatternName),
+    #[error("pattern {0:?} refers to itself")]
+    RecursivePattern(PatternName),
+    #[error("events that take no time are repeated {0} times")]
+    InstantRepeat(Ticks),
+}
+
+/// Problems that make the whole config unusable.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct InvalidConfig(pub Vec<ConfigError>);
+
+struct Validator<'a> {
+    config: &'a MusicConfig,
+    errors: Vec<ConfigError>,
+}
+
+impl MusicConfig {
+    /// Checks the config for problems, collecting all of them.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut validator = Validator {
+            config: self,
+            errors: Vec::new(),
+        };
+        validator.validate();
+        if validator.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(validator.errors)
+        }
+    }
+
+    /// Validates the config, logging every problem found,
+    /// and removes the sections that failed validation.
+    /// An invalid metronome sample falls back to the generated clicks,
+    /// an invalid sfx config leaves only the generated sound effects.
+    /// Returns the problems that cannot be recovered from, e.g. zero ticks per beat.
+    pub fn skip_invalid_sections(&mut self) -> Result<(), InvalidConfig> {
+        let Err(errors) = self.validate() else {
+            return Ok(());
+        };
+        let (fatal, errors): (Vec<_>, Vec<_>) =
+            errors.into_iter().partition(|error| error.is_fatal());
+        for error in errors {
+            log::error!("Invalid music config: {error}");
+            if let Some(section) = error.section {
+                self.sections.remove(&section);
+            } else if error.path.starts_with("metronome.sample") {
+                self.metronome.sample = None;
+            } else if error.path.starts_with("sfx") {
+                self.sfx.soundfont = None;
+                self.sfx
+                    .sounds
+                    .retain(|_, sound| matches!(sound, SfxSound::Click { .. }));
+            }
+        }
+        if fatal.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig(fatal))
+        }
+    }
+}
+
+impl ConfigError {
+    /// Whether the problem makes the whole config unusable.
+    pub fn is_fatal(&self) -> bool {
+        self.section.is_none()
+            && matches!(
+                self.kind,
+                ConfigErrorKind::ZeroTicksPerBeat
+                    | ConfigErrorKind::ZeroBeatsPerBar
+                    | ConfigErrorKind::InvalidBpmRange(..)
+                    | ConfigErrorKind::BpmOutOfRange(..)
+                    | ConfigErrorKind::NonPositiveBpm(..)
+                    | ConfigErrorKind::NegativeBpmChange(..)
+                    | ConfigErrorKind::SmoothingFactorOutOfRange(..)
+            )
+    }
+}
+
+impl<'c> Validator<'c> {
+    fn error(&mut self, path: String, section: Option<&SectionName>, kind: ConfigErrorKind) {
+        self.errors.push(ConfigError {
+            path,
+            section: section.cloned(),
+            kind,
+        });
+    }
+
+    fn validate(&mut self) {
+        let config = self.config;
+
+        if config.ticks_per_beat == 0 {
+            self.error(
+                "ticks_per_beat".to_owned(),
+                None,
+                ConfigErrorKind::ZeroTicksPerBeat,
+            );
+        }
+        if config.meter.beats_per_bar == 0 {
+            self.error(
+                "meter.beats_per_bar".to_owned(),
+                None,
+                ConfigErrorKind::ZeroBeatsPerBar,
+            );
+        }
+
+        if let Some(sample) = &config.metronome.sample {
+            if !config.soundfonts.contains_key(&sample.soundfont.name) {
+                self.error(
+                    "metronome.sample.soundfont".to_owned(),
+                    None,
+                    ConfigErrorKind::UnknownSoundfont(sample.soundfont.name.clone()),
+                );
+            }
+            for (field, note) in [("accent", sample.accent), ("note", sample.note)] {
+                if !note.is_valid_midi() {
+                    self.error(
+                        format!("metronome.sample.{field}"),
+                        None,
+                        ConfigErrorKind::NoteOutOfRange(note),
+                    );
+                }
+            }
+            self.validate_velocity(
+                "metronome.sample.velocity".to_owned(),
+                None,
+                sample.velocity,
+            );
+        }
+
+        if let Some(soundfont) = &config.sfx.soundfont {
+            if !config.soundfonts.contains_key(&soundfont.name) {
+                self.error(
+                    "sfx.soundfont".to_owned(),
+                    None,
+                    ConfigErrorKind::UnknownSoundfont(soundfont.name.clone()),
+                );
+            }
+        }
+        let mut sounds: Vec<_> = config.sfx.sounds.iter().collect();
+        sounds.sort_by_key(|&(event, _)| format!("{event:?}"));
+        for (event, sound) in sounds {
+            if let SfxSound::Note { note, velocity, .. } = *sound {
+                let path = format!("sfx.sounds.{event:?}");
+                if !note.is_valid_midi() {
+                    self.error(
+                        format!("{path}.note"),
+                        None,
+                        ConfigErrorKind::NoteOutOfRange(note),
+                    );
+                }
+                self.validate_velocity(format!("{path}.velocity"), None, velocity);
+            }
+        }
+
+        let [bpm_min, bpm_max] = [config.beat.bpm_min, config.beat.bpm_max];
+        if bpm_min > bpm_max {
+            self.error(
+                "beat".to_owned(),
+                None,
+                ConfigErrorKind::InvalidBpmRange(bpm_min, bpm_max),
+            );
+        } else {
+            let mut bpms = vec![("bpm".to_owned(), config.bpm)];
+            if let TempoMode::Locked { bpm } = config.beat.tempo {
+                bpms.push(("beat.tempo.bpm".to_owned(), bpm));
+            }
+            for (path, bpm) in bpms {
+                if bpm <= 0.0 {
+                    self.error(path, None, ConfigErrorKind::NonPositiveBpm(bpm));
+                } else if !(bpm_min as f32..=bpm_max as f32).contains(&bpm) {
+                    self.error(
+                        path,
+                        None,
+                        ConfigErrorKind::BpmOutOfRange(bpm, bpm_min, bpm_max),
+                    );
+                }
+            }
+        }
+        if let Some(max_change) = config.beat.max_bpm_change {
+            if max_change < 0.0 {
+                self.error(
+                    "beat.max_bpm_change".to_owned(),
+                    None,
+                    ConfigErrorKind::NegativeBpmChange(max_change),
+                );
+            }
+        }
+        if let BpmSmoothing::Exponential { factor } = config.beat.smoothing {
+            if !(0.0..=1.0).contains(&factor) {
+                self.error(
+                    "beat.smoothing.factor".to_owned(),
+                    None,
+                    ConfigErrorKind::SmoothingFactorOutOfRange(factor),
+                );
+            }
+        }
+
+        let mut sections: Vec<_> = config.sections.iter().collect();
+        sections.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for &(name, section) in &sections {
+            self.validate_section(name, section);
+        }
+
+        self.validate_bpm_coverage(&sections, [bpm_min, bpm_max]);
+    }
+
+    fn validate_section(&mut self, name: &'c SectionName, section: &'c SectionConfig) {
+        let path = format!("sections.{name}");
+        let name = Some(name);
+
+        if !self.config.soundfonts.contains_key(&section.soundfont.name) {
+            self.error(
+                format!("{path}.soundfont"),
+                name,
+                ConfigErrorKind::UnknownSoundfont(section.soundfont.name.clone()),
+            );
+        }
+
+        let [min, max] = section.bpm_range;
+        if min > max {
+            self.error(
+                format!("{path}.bpm_range"),
+                name,
+                ConfigErrorKind::InvalidBpmRange(min, max),
+            );
+        }
+
+        if !section.key.is_valid_midi() {
+            self.error(
+                format!("{path}.key"),
+                name,
+                ConfigErrorKind::NoteOutOfRange(section.key),
+            );
+            // Notes relative to the key cannot be checked
+            return;
+        }
+
+        self.validate_velocity(
+            format!("{path}.default_velocity"),
+            name,
+            section.default_velocity,
+        );
+
+        if section.events.is_empty() {
+            self.error(
+                format!("{path}.events"),
+                name,
+                ConfigErrorKind::EmptySection,
+            );
+        }
+        let mut patterns = Vec::new();
+        let mut default_duration = section.default_duration;
+        self.validate_events(
+            &format!("{path}.events"),
+            name,
+            section.key,
+            section.events.iter(),
+            &mut default_duration,
+            &mut patterns,
+        );
+    }
+
+    /// Returns the duration of the events in ticks, as played by the music controller.
+    /// `default_duration` is updated by the `ChangeDefaultDuration` events.
+    fn validate_events(
+        &mut self,
+        path: &str,
+        section: Option<&SectionName>,
+        key: Note,
+        events: impl IntoIterator<Item = &'c SectionEvent>,
+        default_duration: &mut Ticks,
+        patterns: &mut Vec<&'c PatternName>,
+    ) -> Ticks {
+        let mut duration: Ticks = 0;
+        for (i, event) in events.into_iter().enumerate() {
+            let path = format!("{path}[{i}]");
+            match event {
+                SectionEvent::Delay { delay } => {
+                    duration = duration.saturating_add(*delay);
+                }
+                SectionEvent::NoteDefault(note) => {
+                    duration = duration.saturating_add(*default_duration);
+                    self.validate_note(path, section, key, *note);
+                }
+                SectionEvent::Note(note) => {
+                    duration = duration.saturating_add(note.duration.unwrap_or(*default_duration));
+                    self.validate_note(format!("{path}.note"), section, key, note.note);
+                    if let Some(velocity) = note.velocity {
+                        self.validate_velocity(format!("{path}.velocity"), section, velocity);
+                    }
+                }
+                SectionEvent::Chord(chord) => {
+                    duration = duration.saturating_add(chord.duration.unwrap_or(*default_duration));
+                    if let Some(velocity) = chord.velocity {
+                        self.validate_velocity(format!("{path}.velocity"), section, velocity);
+                    }
+                    match &chord.chord {
+                        ChordSpec::Notes(notes) => {
+                            for (j, note) in notes.iter().enumerate() {
+                                let path = format!("{path}.chord[{j}]");
+                                match *note {
+                                    ChordNote::Note(note) => {
+                                        self.validate_note(path, section, key, note);
+                                    }
+                                    ChordNote::Full { note, velocity } => {
+                                        self.validate_note(
+                                            format!("{path}.note"),
+                                            section,
+                                            key,
+                                            note,
+                                        );
+                                        if let Some(velocity) = velocity {
+                                            self.validate_velocity(
+                                                format!("{path}.velocity"),
+                                                section,
+                                                velocity,
+                                            );
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        ChordSpec::Name(name) => {
+                            let root = Note {
+                                octave: name.octave.unwrap_or(key.octave),
+                                note: name.root,
+                            };
+                            for &delta in name.quality.intervals() {
+                                self.validate_note(
+                                    format!("{path}.chord"),
+                                    section,
+                                    root,
+                                    NoteSpec::KeyDelta(delta),
+                                );
+                            }
+                        }
+                    }
+                }
+                SectionEvent::Tagged(tagged) => match tagged {
+                    SectionEventTagged::ChangeDefaultVelocity { value } => {
+                        self.validate_velocity(format!("{path}.value"), section, *value);
+                    }
+                    SectionEventTagged::ChangeDefaultDuration { value } => {
+                        *default_duration = *value;
+                    }
+                    SectionEventTagged::Repeat { count, events } => {
+                        let repeated = self.validate_events(
+                            &format!("{path}.events"),
+                            section,
+                            key,
+                            events,
+                            default_duration,
+                            patterns,
+                        );
+                        // The music controller expands all iterations of a body
+                        // that takes no time within a single tick
+                        if *count > MAX_INSTANT_REPEATS && repeated == 0 {
+                            self.error(
+                                format!("{path}.count"),
+                                section,
+                                ConfigErrorKind::InstantRepeat(*count),
+                            );
+                        }
+                        duration = duration.saturating_add(repeated.saturating_mul(*count));
+                    }
+                    SectionEventTagged::Pattern { name } => {
+                        let config = self.config;
+                        let Some(events) = config.patterns.get(name) else {
+                            self.error(
+                                format!("{path}.name"),
+                                section,
+                                ConfigErrorKind::UnknownPattern(name.clone()),
+                            );
+                            continue;
+                        };
+                        if patterns.contains(&name) {
+                            self.error(
+                                format!("{path}.name"),
+                                section,
+                                ConfigErrorKind::RecursivePattern(name.clone()),
+                            );
+                            continue;
+                        }
+                        patterns.push(name);
+                        let pattern_duration = self.validate_events(
+                            &format!("patterns.{name}"),
+                            section,
+                            key,
+                            events,
+                            default_duration,
+                            patterns,
+                        );
+                        patterns.pop();
+                        duration = duration.saturating_add(pattern_duration);
+                    }
+                },
+            }
+        }
+        duration
+    }
+
+    fn validate_note(
+        &mut self,
+        path: String,
+        section: Option<&SectionName>,
+        key: Note,
+        note: NoteSpec,
+    ) {
+        match note {
+            NoteSpec::KeyDelta(delta) => {
+                if key.checked_shift(delta).is_none() {
+                    self.error(
+                        path,
+                        section,
+                        ConfigErrorKind::ShiftOutOfRange { key, delta },
+                    );
+                }
+            }
+            NoteSpec::Note(note) => {
+                if !note.is_valid_midi() {
+                    self.error(path, section, ConfigErrorKind::NoteOutOfRange(note));
+                }
+            }
+        }
+    }
+
+    fn validate_velocity(&mut self, path: String, section: Option<&SectionName>, velocity: u32) {
+        if velocity > MIDI_MAX as u32 {
+            self.error(path, section, ConfigErrorKind::VelocityOutOfRange(velocity));
+        }
+    }
+
+    /// Checks that every BPM in `bpm_range` is covered by a section,
+    /// and that no two sections share more than a boundary value.
+    fn validate_bpm_coverage(
+        &mut self,
+        sections: &[(&SectionName, &SectionConfig)],
+        [bpm_min, bpm_max]: [Ticks; 2],
+    ) {
+        let mut ranges: Vec<_> = sections
+            .iter()
+            .map(|&(name, section)| (name, section.bpm_range))
+            .filter(|(_, [min, max])| min <= max)
+            .collect();
+        ranges.sort_by_key(|&(_, range)| range);
+
+        // Overlaps
+        for (i, &(name, [_, max])) in ranges.iter().enumerate() {
+            for &(other, [other_min, _]) in &ranges[i + 1..] {
+                if other_min < max {
+                    self.error(
+                        format!("sections.{other}.bpm_range"),
+                        Some(other),
+                        ConfigErrorKind::BpmOverlap(name.clone()),
+                    );
+                }
+            }
+        }
+
+        // Gaps
+        let mut covered = bpm_min;
+        for &(_, [min, max]) in &ranges {
+            if covered >= bpm_max {
+                break;
+            }
+            if min > covered {
+                self.error(
+                    "sections".to_owned(),
+                    None,
+                    ConfigErrorKind::BpmNotCovered(covered, min.min(bpm_max)),
+                );
+            }
+            covered = covered.max(max);
+        }
+        if covered < bpm_max {
+            self.error(
+                "sections".to_owned(),
+                None,
+                ConfigErrorKind::BpmNotCovered(covered, bpm_max),
+            );
+        }
+    }
+}
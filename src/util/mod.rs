@@ -12,6 +12,10 @@ pub fn king_distance<T: Num + Ord>(vec: vec2<T>) -> T {
     vec.x.abs().max(vec.y.abs())
 }
 
+pub fn manhattan_distance<T: Num>(vec: vec2<T>) -> T {
+    vec.x.abs() + vec.y.abs()
+}
+
 /// Normalizes arbitrary vector to one of 9 possible directions from the grid.
 pub fn vec_to_dir(vec: vec2<f32>) -> vec2<i64> {
     let tangent = vec.y.abs() / vec.x.abs().max(1e-2);
@@ -27,6 +31,21 @@ pub fn vec_to_dir(vec: vec2<f32>) -> vec2<i64> {
     }
 }
 
+/// Returns the cells of the grid line from `from` to `to`, including both ends.
+pub fn grid_line(from: vec2<i64>, to: vec2<i64>) -> Vec<vec2<i64>> {
+    let delta = to - from;
+    let steps = king_distance(delta);
+    if steps == 0 {
+        return vec![from];
+    }
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            from + delta.map(|x| (x as f32 * t).round() as i64)
+        })
+        .collect()
+}
+
 /// Calculates distance from a point to a segment.
 pub fn dist_to_segment<T: Float>(p: vec2<T>, s: Segment<T>) -> T {
     let dir = s.1 - s.0;
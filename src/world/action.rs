@@ -5,29 +5,34 @@ pub struct ActionInput {
     pub target: EffectTarget,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     Move(ActionMove),
     UseItem(ActionUseItem),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ActionUseItem {
     pub item: ItemId,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ActionMove {
     Slide(MoveSlide),
     Teleport(MoveTeleport),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MoveSlide {
     pub delta: vec2<Coord>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MoveTeleport {
     pub target: vec2<Coord>,
 }
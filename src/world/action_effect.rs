@@ -1,12 +1,14 @@
 use super::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ActionEffect {
     pub aim: ActionAim,
     pub effect: Effect,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ActionAim {
     InRange { distance: Coord },
 }
@@ -15,12 +17,7 @@ impl ActionAim {
     /// Check whether `target` satisfies the constraints.
     pub fn check(&self, world: &World, unit: UnitId, target: EffectTarget) -> SystemResult<bool> {
         match self {
-            &ActionAim::InRange { distance } => {
-                let target_pos = target.find_pos(world)?;
-                let &caster_pos = world.units.grid_position.get(unit).expect("Unit not found");
-                let delta = target_pos - caster_pos;
-                Ok(crate::util::king_distance(delta) <= distance)
-            }
+            &ActionAim::InRange { distance } => Ok(world.distance_to(unit, target)? <= distance),
         }
     }
 
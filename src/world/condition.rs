@@ -1,6 +1,7 @@
 use super::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Condition {
     TargetIsUnit,
 }
@@ -1,39 +1,78 @@
 use super::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Effect {
     Noop,
     If(Box<EffectIf>),
     Damage(Box<EffectDamage>),
     Projectile(Box<EffectProjectile>),
     Particles(Box<EffectParticles>),
+    Note(Box<EffectNote>),
+    Summon(Box<EffectSummon>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EffectIf {
     pub condition: Condition,
     pub then: Effect,
     pub otherwise: Effect,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EffectDamage {
     pub value: Hp,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EffectProjectile {
     pub projectile: ProjectilePrefab,
     pub speed: FCoord,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EffectParticles {
     pub pos: vec2<Coord>,
     pub color: Color,
 }
 
+/// Plays a note along with the music.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EffectNote {
+    pub note: GameNote,
+}
+
+/// Spawns a unit at the target cell, on the caster's side and following the caster.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EffectSummon {
+    pub unit: UnitPrefab,
+}
+
 impl Effect {
+    /// Substitutes the named behaviours of the units summoned by the effect.
+    pub fn resolve_behaviours(
+        &mut self,
+        behaviours: &HashMap<BehaviourName, UnitBehaviour>,
+    ) -> Result<(), String> {
+        match self {
+            Effect::Noop | Effect::Damage(_) | Effect::Particles(_) | Effect::Note(_) => Ok(()),
+            Effect::If(effect) => {
+                effect.then.resolve_behaviours(behaviours)?;
+                effect.otherwise.resolve_behaviours(behaviours)
+            }
+            Effect::Projectile(effect) => {
+                effect.projectile.on_contact.resolve_behaviours(behaviours)
+            }
+            Effect::Summon(effect) => effect.unit.resolve_behaviours(behaviours),
+        }
+    }
+
     pub fn apply(self, logic: &mut Logic<'_>, context: EffectContext) -> SystemResult<()> {
         log::debug!("Applying effect {self:?} with context {context:?}");
         match self {
@@ -42,6 +81,8 @@ impl Effect {
             Effect::Damage(effect) => effect.apply(logic, context),
             Effect::Projectile(effect) => effect.apply(logic, context),
             Effect::Particles(effect) => effect.apply(logic, context),
+            Effect::Note(effect) => effect.apply(logic, context),
+            Effect::Summon(effect) => effect.apply(logic, context),
         }
     }
 }
@@ -62,7 +103,8 @@ impl EffectDamage {
         let target = context.expect_target()?;
         match target.find_unit(logic.world) {
             Ok(unit) => {
-                logic.unit_damage(unit, self.value)?;
+                let source = context.caster.as_ref().map(|caster| caster.unit);
+                logic.unit_damage(unit, self.value, source)?;
             }
             Err(_) => {
                 let pos = target.find_pos(logic.world)?;
@@ -111,6 +153,7 @@ impl EffectProjectile {
         };
         let projectile = self.projectile.instantiate(inst);
         logic.world.projectiles.insert(projectile);
+        logic.world.play_sfx(SfxEvent::ProjectileLaunch);
         Ok(())
     }
 }
@@ -121,3 +164,41 @@ impl EffectParticles {
         Ok(())
     }
 }
+
+impl EffectNote {
+    pub fn apply(self, logic: &mut Logic<'_>, _context: EffectContext) -> SystemResult<()> {
+        logic.world.music_controller.queue_note(self.note);
+        Ok(())
+    }
+}
+
+impl EffectSummon {
+    pub fn apply(self, logic: &mut Logic<'_>, context: EffectContext) -> SystemResult<()> {
+        let caster = context.expect_caster()?;
+        let &fraction = logic
+            .world
+            .units
+            .fraction
+            .get(caster.unit)
+            .expect("Unit not found");
+
+        let target = context.expect_target()?;
+        let grid_position = target.find_pos(logic.world)?;
+        if !self.unit.fits(&logic.world.units, grid_position) {
+            log::debug!("Tried summoning into an occupied cell");
+            return Ok(());
+        }
+
+        let inst = UnitInst {
+            grid_position,
+            world_position: logic.world.grid.grid_to_world(grid_position),
+            fraction,
+            leader: Some(caster.unit),
+        };
+        let unit = self.unit.instantiate(inst);
+        logic.world.units.insert(unit);
+        let color = logic.world.fractions.color(fraction);
+        logic.world.spawn_particles(grid_position, color)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use super::*;
+
+/// Index of a fraction in [`Fractions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fraction(usize);
+
+pub type FractionName = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    Ally,
+    Neutral,
+    Hostile,
+}
+
+#[derive(Debug, Clone, Deserialize, geng::Load)]
+#[load(json)]
+#[serde(deny_unknown_fields)]
+pub struct FractionsConfig {
+    /// The fraction of the player's unit.
+    pub player: FractionName,
+    /// Relation between different fractions unless specified in `relations`.
+    pub default_relation: Relation,
+    pub fractions: Vec<FractionConfig>,
+    /// Symmetric relations between pairs of fractions.
+    #[serde(default)]
+    pub relations: Vec<RelationConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FractionConfig {
+    pub name: FractionName,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelationConfig {
+    pub between: [FractionName; 2],
+    pub relation: Relation,
+}
+
+/// All fractions of the world and the relations between them.
+#[derive(Debug, Clone)]
+pub struct Fractions {
+    player: Fraction,
+    names: Vec<FractionName>,
+    colors: Vec<Color>,
+    /// Relation matrix indexed by the fractions.
+    relations: Vec<Vec<Relation>>,
+}
+
+pub type FractionResult<T> = Result<T, FractionError>;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum FractionError {
+    #[error("Fraction {0:?} is defined more than once")]
+    Duplicate(FractionName),
+    #[error("Unknown fraction {0:?}")]
+    Unknown(FractionName),
+}
+
+impl Fractions {
+    pub fn new(config: FractionsConfig) -> FractionResult<Self> {
+        let mut names: Vec<FractionName> = Vec::new();
+        let mut colors = Vec::new();
+        for fraction in config.fractions {
+            if names.contains(&fraction.name) {
+                return Err(FractionError::Duplicate(fraction.name));
+            }
+            names.push(fraction.name);
+            colors.push(fraction.color);
+        }
+
+        let find = |name: &FractionName| {
+            names
+                .iter()
+                .position(|other| other == name)
+                .map(Fraction)
+                .ok_or_else(|| FractionError::Unknown(name.clone()))
+        };
+
+        let player = find(&config.player)?;
+        let mut relations = vec![vec![config.default_relation; names.len()]; names.len()];
+        for (i, row) in relations.iter_mut().enumerate() {
+            // A fraction is always allied with itself
+            row[i] = Relation::Ally;
+        }
+        for relation in &config.relations {
+            let Fraction(a) = find(&relation.between[0])?;
+            let Fraction(b) = find(&relation.between[1])?;
+            relations[a][b] = relation.relation;
+            relations[b][a] = relation.relation;
+        }
+
+        Ok(Self {
+            player,
+            names,
+            colors,
+            relations,
+        })
+    }
+
+    /// The fraction of the player's unit.
+    pub fn player(&self) -> Fraction {
+        self.player
+    }
+
+    pub fn find(&self, name: &str) -> Option<Fraction> {
+        self.names
+            .iter()
+            .position(|other| other == name)
+            .map(Fraction)
+    }
+
+    pub fn name(&self, fraction: Fraction) -> &str {
+        &self.names[fraction.0]
+    }
+
+    pub fn color(&self, fraction: Fraction) -> Color {
+        self.colors[fraction.0]
+    }
+
+    /// How the `fraction` treats the `other` fraction.
+    pub fn relation(&self, fraction: Fraction, other: Fraction) -> Relation {
+        self.relations[fraction.0][other.0]
+    }
+}
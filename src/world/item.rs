@@ -1,19 +1,25 @@
 use super::*;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct HeldItems {
     pub left_hand: Option<Item>,
     pub right_hand: Option<Item>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Item {
     pub on_use: ActionEffect,
+    /// Note played along with the music when the item is used.
+    #[serde(default)]
+    pub note: Option<GameNote>,
 }
 
 pub type ItemId = HandId;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum HandId {
     LeftHand,
     RightHand,
@@ -53,6 +59,12 @@ impl Item {
                 aim: ActionAim::InRange { distance: 1 },
                 effect: Effect::Damage(Box::new(EffectDamage { value: damage })),
             },
+            note: Some(GameNote {
+                note: NoteSpec::KeyDelta(0),
+                program: None,
+                velocity: None,
+                duration: Some(2),
+            }),
         }
     }
 
@@ -72,6 +84,28 @@ impl Item {
                     speed,
                 })),
             },
+            note: Some(GameNote {
+                note: NoteSpec::KeyDelta(7),
+                program: None,
+                velocity: None,
+                duration: Some(1),
+            }),
+        }
+    }
+
+    /// Summons the unit, which follows the user if it has the `UnitBehaviour::Leash`.
+    pub fn skull(unit: UnitPrefab) -> Self {
+        Self {
+            on_use: ActionEffect {
+                aim: ActionAim::InRange { distance: 1 },
+                effect: Effect::Summon(Box::new(EffectSummon { unit })),
+            },
+            note: Some(GameNote {
+                note: NoteSpec::KeyDelta(-5),
+                program: None,
+                velocity: None,
+                duration: Some(4),
+            }),
         }
     }
 }
@@ -38,22 +38,56 @@ impl World {
         let ticks = self.beat_controller.update(delta_time.as_f32());
         self.music_controller
             .set_bpm(self.beat_controller.get_bpm());
-        for _ in 0..ticks {
-            self.music_controller.tick();
-        }
+        let first_tick = self.beat_controller.get_tick() + 1 - ticks.len() as Ticks;
+        for (tick, time) in (first_tick..).zip(ticks) {
+            self.music_controller.tick(time);
+            self.tick_spawner();
+
+            let mut logic = Logic {
+                world: self,
+                delta_time: Time::ZERO,
+                queued_effects: default(),
+            };
+            logic.process_units_music_tick(tick)?;
+            logic.process_effects()?;
 
-        // Play music
-        let audio = self.geng.audio();
-        for sound in self.music_controller.update(delta_time.as_f32()) {
-            let sample_rate = rodio::Source::sample_rate(&sound) as f32;
-            let data = sound.data().as_ref().to_owned();
-            audio.from_raw(data, sample_rate).play();
+            let position = self.music_controller.schedule_position(time);
+            self.sfx_controller.tick(position);
+        }
+        if let Some(output) = &mut self.music_output {
+            output.update(delta_time.as_f32());
+        } else {
+            // Nothing plays the mixer, so keep it in step with the music
+            self.music_controller.drain_mixer(delta_time.as_f32());
         }
+        self.music_controller.update(delta_time.as_f32());
 
         Ok(())
     }
 }
 
+impl World {
+    /// Advances the spawner by one music tick.
+    fn tick_spawner(&mut self) {
+        let event = self.spawner.tick(
+            &mut self.units,
+            &self.grid,
+            &self.beat_controller,
+            self.score,
+        );
+        match event {
+            Some(WaveEvent::Started(wave)) => {
+                log::debug!("Wave {} started", wave + 1);
+            }
+            Some(WaveEvent::Cleared(wave)) => {
+                log::info!("Wave {} cleared", wave + 1);
+                self.play_sfx(SfxEvent::WaveCleared);
+            }
+            None => {}
+        }
+    }
+}
+
 impl Logic<'_> {
     pub fn process(
         &mut self,
@@ -67,6 +101,7 @@ impl Logic<'_> {
         self.process_projectiles_collide()?;
 
         self.process_units_pos()?;
+        self.process_units_threat();
         self.process_units_ai()?;
 
         self.process_particles();
@@ -85,13 +120,24 @@ impl Logic<'_> {
 
     pub fn player_action(&mut self, action: PlayerAction, input: ActionInput) -> SystemResult<()> {
         self.world.player_beat_time = Time::ZERO;
-        let ticks = self.world.beat_controller.player_beat();
-        for _ in 0..ticks {
-            self.world.music_controller.tick();
+        let beat = self.world.beat_controller.player_beat();
+        self.world.music_controller.skip_ticks(beat.skip_ticks);
+        // The units following the music act on the skipped ticks right away
+        let tick = self.world.beat_controller.get_tick();
+        for skipped in tick + 1 - beat.skip_ticks..=tick {
+            self.process_units_music_tick(skipped)?;
         }
 
-        // TODO: validate action
-        self.unit_action(self.world.player.unit, action, input)?;
+        match beat.judgement {
+            BeatJudgement::Hit => {
+                // TODO: validate action
+                self.unit_action(self.world.player.unit, action, input)?;
+            }
+            BeatJudgement::Miss => {
+                log::debug!("Player missed the beat, action {action:?} is ignored");
+                self.world.play_sfx(SfxEvent::Miss);
+            }
+        }
 
         // Synchronize units
         for (_, unit) in self.world.units.unit.iter_mut() {
@@ -165,13 +211,17 @@ impl Logic<'_> {
 
         let target = pos + slide.delta;
 
-        let other = self
+        let footprint = self
             .world
             .units
-            .grid_position
-            .iter()
-            .find(|(_, &pos)| pos == target);
-        if let Some((other, _)) = other {
+            .footprint
+            .get(unit)
+            .expect("Unit not found");
+        let other = footprint
+            .cells(target)
+            .filter_map(|cell| find_unit_at(&self.world.units, cell))
+            .find(|&other| other != unit);
+        if let Some(other) = other {
             self.contact_damage(unit, other)?;
             return Ok(());
         }
@@ -187,12 +237,22 @@ impl Logic<'_> {
 
     pub fn contact_damage(&mut self, unit_a: UnitId, unit_b: UnitId) -> SystemResult<()> {
         // TODO: customize damage
-        self.unit_damage(unit_a, Hp::new(1.0))?;
-        self.unit_damage(unit_b, Hp::new(1.0))?;
+        self.unit_damage(unit_a, Hp::new(1.0), Some(unit_b))?;
+        self.unit_damage(unit_b, Hp::new(1.0), Some(unit_a))?;
         Ok(())
     }
 
-    pub fn unit_damage(&mut self, unit: UnitId, damage: Hp) -> SystemResult<()> {
+    /// Damages the unit, counting the damage towards the `source`'s threat.
+    pub fn unit_damage(
+        &mut self,
+        unit: UnitId,
+        damage: Hp,
+        source: Option<UnitId>,
+    ) -> SystemResult<()> {
+        if let Some(threat) = source.and_then(|source| self.world.units.threat.get_mut(source)) {
+            *threat += damage;
+        }
+
         let &pos = self
             .world
             .units
@@ -210,13 +270,49 @@ impl Logic<'_> {
         if health.is_dead() {
             // Unit died
             // TODO: death effect
-            self.world.units.remove(unit);
+            let dead = self.world.units.remove(unit).expect("Unit not found");
+            let fractions = &self.world.fractions;
+            if fractions.relation(fractions.player(), dead.fraction) == Relation::Hostile {
+                self.world.score += 1;
+            }
+            self.world.play_sfx(SfxEvent::Death);
+        } else {
+            self.world.play_sfx(SfxEvent::Hit);
+            self.unit_update_phase(unit);
         }
 
         self.world.spawn_particles(pos, Color::WHITE)?;
         Ok(())
     }
 
+    /// Switches the unit to the latest phase its health has reached.
+    fn unit_update_phase(&mut self, unit: UnitId) {
+        let ratio = self
+            .world
+            .units
+            .health
+            .get(unit)
+            .expect("Unit not found")
+            .get_ratio();
+        let Some(Some(ai)) = self.world.units.unit.get_mut(unit) else {
+            return;
+        };
+        let reached = ai
+            .phases
+            .iter()
+            .take_while(|phase| ratio <= phase.health_ratio)
+            .count();
+        let Some(phase) = ai.phases.drain(..reached).last() else {
+            return;
+        };
+        log::debug!("Unit {unit:?} entered a new phase");
+        ai.beat = phase.beat;
+        ai.behaviour = phase.behaviour;
+        ai.blackboard = default();
+        // The old intent no longer matches the behaviour
+        ai.intent = None;
+    }
+
     pub fn unit_use_item(
         &mut self,
         unit: UnitId,
@@ -235,7 +331,12 @@ impl Logic<'_> {
         };
 
         let action = item.on_use.clone();
+        let note = item.note;
         let (effect, context) = action.into_effect(self.world, unit, input)?;
+        if let Some(note) = note {
+            // Play along with the music
+            Effect::Note(Box::new(EffectNote { note })).apply(self, context.clone())?;
+        }
         effect.apply(self, context)?;
 
         Ok(())
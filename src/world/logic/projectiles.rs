@@ -57,7 +57,8 @@ impl Logic<'_> {
 
         #[derive(StructQuery)]
         struct Unit<'a> {
-            world_position: &'a vec2<FCoord>,
+            grid_position: &'a vec2<Coord>,
+            footprint: &'a Footprint,
             fraction: &'a Fraction,
         }
 
@@ -66,11 +67,16 @@ impl Logic<'_> {
             let query = query_unit!(self.world.units);
             let target = query
                 .iter()
-                .filter(|(_, unit)| proj.target_filter.check(*proj.fraction, *unit.fraction))
+                .filter(|(_, unit)| {
+                    proj.target_filter
+                        .check(&self.world.fractions, *proj.fraction, *unit.fraction)
+                })
                 .find(|(_, unit)| {
-                    let delta = *unit.world_position - *proj.world_position;
-                    let dist = delta.len();
-                    dist.as_f32() < 0.5
+                    unit.footprint.cells(*unit.grid_position).any(|cell| {
+                        let delta = self.world.grid.grid_to_world(cell) - *proj.world_position;
+                        let dist = delta.len();
+                        dist.as_f32() < 0.5
+                    })
                 });
             if let Some((unit_id, _)) = target {
                 self.queued_effects.push_back(QueuedEffect {
@@ -1,5 +1,8 @@
 use super::*;
 
+/// Time in seconds for the threat of a unit to decay by half.
+const THREAT_HALF_LIFE: f32 = 2.0;
+
 impl Logic<'_> {
     pub fn process_units_pos(&mut self) -> SystemResult<()> {
         #[derive(StructQuery)]
@@ -18,6 +21,14 @@ impl Logic<'_> {
         Ok(())
     }
 
+    pub fn process_units_threat(&mut self) {
+        // Halve the threat every `THREAT_HALF_LIFE` seconds
+        let decay = Hp::new(0.5).powf(self.delta_time / Time::new(THREAT_HALF_LIFE));
+        for (_, threat) in self.world.units.threat.iter_mut() {
+            *threat *= decay;
+        }
+    }
+
     pub fn process_units_ai(&mut self) -> SystemResult<()> {
         let mut actions = Vec::new();
         for (id, unit) in self.world.units.unit.iter_mut() {
@@ -25,7 +36,49 @@ impl Logic<'_> {
                 continue;
             };
 
-            let bpm = unit.beat.calc_bpm(self.world.beat_controller.get_bpm());
+            if let UnitBeat::Bars { bars, last_bar } = &mut unit.beat {
+                // Follow the music instead of the timer
+                if *bars == 0 {
+                    continue;
+                }
+                let beat_controller = &self.world.beat_controller;
+                let (bar, _) = beat_controller.get_bar_position();
+                let bar_in_cycle = bar % *bars;
+                if bar_in_cycle == 0 && *last_bar != Some(bar) {
+                    *last_bar = Some(bar);
+                    actions.push(id);
+                }
+                let progress =
+                    (bar_in_cycle as f32 + beat_controller.get_bar_progress()) / *bars as f32;
+                unit.next_beat = Time::new(1.0 - progress);
+                continue;
+            }
+
+            if let UnitBeat::MusicTicks { cycle, ticks } = &unit.beat {
+                // Acts in `process_units_music_tick`, only show the time until the next beat
+                let cycle = *cycle;
+                if cycle == 0 || ticks.is_empty() {
+                    continue;
+                }
+                let beat_controller = &self.world.beat_controller;
+                let pos = beat_controller.get_tick() % cycle;
+                // Ticks since the last and until the next beat of the unit
+                let since = ticks
+                    .iter()
+                    .map(|&t| (pos + cycle - t % cycle) % cycle)
+                    .min()
+                    .unwrap_or(0);
+                let until = ticks
+                    .iter()
+                    .map(|&t| (t % cycle + cycle - pos - 1) % cycle + 1)
+                    .min()
+                    .unwrap_or(cycle);
+                let progress = beat_controller.get_tick_progress();
+                unit.next_beat = Time::new((until as f32 - progress) / (since + until) as f32);
+                continue;
+            }
+
+            let bpm = unit.beat.calc_bpm(&self.world.beat_controller);
             let beat_time = Time::new(60.0 / bpm);
 
             if let UnitBeat::Synchronized { .. } = unit.beat {
@@ -42,17 +95,67 @@ impl Logic<'_> {
             }
         }
 
+        self.units_act(actions)
+    }
+
+    /// Makes the `UnitBeat::MusicTicks` units that beat on the music `tick` act.
+    pub fn process_units_music_tick(&mut self, tick: Ticks) -> SystemResult<()> {
+        let mut actions = Vec::new();
+        for (id, unit) in self.world.units.unit.iter() {
+            let Some(UnitAI {
+                beat: UnitBeat::MusicTicks { cycle, ticks },
+                ..
+            }) = unit
+            else {
+                continue;
+            };
+            if *cycle > 0 && ticks.iter().any(|&t| t % cycle == tick % cycle) {
+                actions.push(id);
+            }
+        }
+        self.units_act(actions)
+    }
+
+    /// Makes the units act on their intents and decide the next ones.
+    fn units_act(&mut self, actions: Vec<UnitId>) -> SystemResult<()> {
         for id in actions {
-            let unit = self
-                .world
-                .units
-                .unit
-                .get(id)
-                .expect("Unit not found")
-                .as_ref()
-                .expect("Unit AI not found");
-            if let Some((action, input)) = unit.behaviour.evaluate(self.world, id)? {
-                self.unit_action(id, action, input)?;
+            let Some(Some(ai)) = self.world.units.unit.get_mut(id) else {
+                // Killed by an earlier action on this beat
+                continue;
+            };
+
+            // Act on the intent decided on the previous beat
+            let intent = ai.intent.take();
+            if let Some(intent) = intent {
+                self.unit_action(id, intent.action, intent.input)?;
+                if self.world.units.unit.get(id).is_none() {
+                    // Unit died
+                    continue;
+                }
+            }
+
+            // Decide the next action
+            // Take out the blackboard while the world is borrowed
+            let ai = self.world.units.unit.get_mut(id).expect("Unit not found");
+            let ai = ai.as_mut().expect("Unit AI not found");
+            let mut blackboard = std::mem::take(&mut ai.blackboard);
+
+            let ai = self.world.units.unit.get(id).expect("Unit not found");
+            let ai = ai.as_ref().expect("Unit AI not found");
+            let decision = ai.behaviour.evaluate(self.world, id, &mut blackboard);
+
+            let ai = self.world.units.unit.get_mut(id).expect("Unit not found");
+            ai.as_mut().expect("Unit AI not found").blackboard = blackboard;
+
+            if let Some((action, input)) = decision? {
+                // Aim at the cell rather than the unit, so that the target can dodge
+                let target = EffectTarget::Position(input.target.find_pos(self.world)?);
+                let intent = UnitIntent {
+                    action,
+                    input: ActionInput { target },
+                };
+                let ai = self.world.units.unit.get_mut(id).expect("Unit not found");
+                ai.as_mut().expect("Unit AI not found").intent = Some(intent);
             }
         }
 
@@ -2,11 +2,47 @@ use super::*;
 
 impl World {
     pub fn get_unit_at(&self, target_pos: vec2<Coord>) -> ContextResult<UnitId> {
-        let target = self
-            .units
-            .grid_position
-            .iter()
-            .find(|(_, &pos)| pos == target_pos);
-        target.map(|(id, _)| id).ok_or(ContextError::NoTarget) // TODO: better error
+        find_unit_at(&self.units, target_pos).ok_or(ContextError::NoTarget) // TODO: better error
+    }
+
+    /// Returns the king distance between the closest cells of the unit and the target.
+    pub fn distance_to(&self, unit: UnitId, target: EffectTarget) -> ComponentResult<Coord> {
+        self.footprint_distance(unit, target, crate::util::king_distance)
+    }
+
+    /// Returns the manhattan distance between the closest cells of the unit and the target.
+    pub fn manhattan_distance_to(
+        &self,
+        unit: UnitId,
+        target: EffectTarget,
+    ) -> ComponentResult<Coord> {
+        self.footprint_distance(unit, target, crate::util::manhattan_distance)
+    }
+
+    fn footprint_distance(
+        &self,
+        unit: UnitId,
+        target: EffectTarget,
+        metric: fn(vec2<Coord>) -> Coord,
+    ) -> ComponentResult<Coord> {
+        let cells = |unit: UnitId| {
+            let &pos = self.units.grid_position.get(unit).expect("Unit not found");
+            let footprint = self.units.footprint.get(unit).expect("Unit not found");
+            footprint.cells(pos).collect::<Vec<_>>()
+        };
+        let target_cells = match target {
+            EffectTarget::Unit(target) => cells(target),
+            EffectTarget::Position(pos) => vec![pos],
+        };
+        let distance = cells(unit)
+            .into_iter()
+            .flat_map(|cell| {
+                target_cells
+                    .iter()
+                    .map(move |&target| metric(target - cell))
+            })
+            .min()
+            .expect("Footprint is empty");
+        Ok(distance)
     }
 }
@@ -4,7 +4,8 @@ use geng::prelude::*;
 use crate::{
     collection::{Collection, Id},
     sound::{
-        BeatConfig, BeatController, MusicConfig, MusicController, SectionName, Synthesizer, Ticks,
+        synthesize, BeatController, BeatJudgement, GameNote, Mixer, MixerOutput, MusicConfig,
+        MusicController, NoteSpec, SfxController, SfxEvent, Synthesizers, Ticks,
     },
 };
 
@@ -14,12 +15,14 @@ mod component;
 mod condition;
 mod context;
 mod effect;
+mod fraction;
 mod grid;
 mod health;
 mod item;
 mod logic;
 mod player;
 mod projectile;
+mod spawner;
 mod target;
 mod unit;
 
@@ -29,12 +32,14 @@ pub use component::*;
 pub use condition::*;
 pub use context::*;
 pub use effect::*;
+pub use fraction::*;
 pub use grid::*;
 pub use health::*;
 pub use item::*;
 pub use logic::*;
 pub use player::*;
 pub use projectile::*;
+pub use spawner::*;
 pub use target::*;
 pub use unit::*;
 
@@ -46,14 +51,21 @@ pub type Color = Rgba<f32>;
 pub struct World {
     pub geng: Geng,
     pub grid: Grid,
+    pub fractions: Fractions,
     pub player: Player,
     pub beat_controller: BeatController,
     pub music_controller: MusicController,
+    pub sfx_controller: SfxController,
+    /// Plays the music while alive, `None` if no audio device is available.
+    pub music_output: Option<MixerOutput>,
     /// Normalized (in range 0..1) time since the last player's beat.
     pub player_beat_time: Time,
     pub units: StructOf<Collection<Unit>>,
     pub projectiles: StructOf<Collection<Projectile>>,
     pub particles: StructOf<Vec<Particle>>,
+    pub spawner: Spawner,
+    /// Number of killed units hostile to the player.
+    pub score: u64,
 }
 
 pub type SystemResult<T> = Result<T, SystemError>;
@@ -78,87 +90,96 @@ pub struct Particle {
     pub color: Color,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Fraction {
-    Player,
-    Enemy,
-}
-
 impl World {
     pub fn new(
         geng: &Geng,
         music_config: MusicConfig,
-        synthesizers: HashMap<SectionName, Synthesizer>,
+        mut synthesizers: Synthesizers,
+        fractions: Fractions,
+        prefabs: &UnitPrefabs,
+        spawner: Spawner,
     ) -> Self {
-        let beat_config = BeatConfig {
-            ticks_per_beat: music_config.ticks_per_beat,
-            ..default()
+        let beat_config = music_config.beat_config();
+        let beat_controller = BeatController::new(beat_config, music_config.bpm);
+
+        let mixer = Mixer::new(synthesize::SAMPLE_RATE as u32);
+        let music_output = match mixer.play(geng) {
+            Ok(output) => Some(output),
+            Err(error) => {
+                log::error!("Failed to play music: {error}");
+                None
+            }
         };
 
+        let sfx_controller = SfxController::new(
+            music_config.sfx.clone(),
+            synthesizers.sfx.take(),
+            mixer.clone(),
+        );
+
         let mut units = StructOf::<Collection<Unit>>::new();
 
         let player_unit = units.insert(Unit {
             grid_position: vec2::ZERO,
             world_position: vec2::ZERO,
+            footprint: Footprint::single(),
             unit: None,
             health: Health::new(Hp::new(10.0)),
-            fraction: Fraction::Player,
+            threat: Hp::ZERO,
+            fraction: fractions.player(),
             held_items: HeldItems {
                 left_hand: Some(Item::bow(Hp::new(1.0), FCoord::new(3.0))),
                 right_hand: Some(Item::sword(Hp::new(2.0))),
             },
         });
 
-        let mut world = Self {
+        let skull = match prefabs.get("skeleton") {
+            Some(skeleton) => Some(Item::skull(skeleton.clone())),
+            None => {
+                log::error!("Skeleton prefab not found, the skull is not available");
+                None
+            }
+        };
+
+        Self {
             geng: geng.clone(),
-            player: Player::new(player_unit),
+            player: Player::new(player_unit, skull),
             grid: Grid::default(),
+            fractions,
             music_controller: MusicController::new(
                 music_config,
-                beat_config.bpm_min as f32,
+                beat_controller.get_bpm(),
                 synthesizers,
+                mixer,
             ),
-            beat_controller: BeatController::new(beat_config),
+            sfx_controller,
+            music_output,
+            beat_controller,
             player_beat_time: Time::ZERO,
             units,
             projectiles: StructOf::new(),
             particles: StructOf::new(),
-        };
-        world.init();
-        world
+            spawner,
+            score: 0,
+        }
     }
 
-    fn init(&mut self) {
-        self.units.insert(Unit {
-            unit: Some(UnitAI {
-                beat: UnitBeat::Synchronized {
-                    unit: 1,
-                    player: 2,
-                    current_beat: 0,
-                },
-                next_beat: Time::ONE,
-                behaviour: UnitBehaviour::SelectTarget {
-                    selector: TargetSelector {
-                        filter: TargetFilter::Fraction(FractionFilter::Enemy),
-                        fitness: TargetFitness::Negative(Box::new(TargetFitness::Distance)),
-                    },
-                    then_behave: Box::new(UnitBehaviour::If {
-                        condition: BehaviourCondition::TargetInRange { distance: 1 },
-                        then_behave: Box::new(UnitBehaviour::UseItemOnTarget {
-                            item: ItemId::RightHand,
-                        }),
-                        else_behave: Box::new(UnitBehaviour::MoveToTarget),
-                    }),
-                },
-            }),
-            fraction: Fraction::Enemy,
-            grid_position: vec2(2, 1),
-            world_position: vec2::ZERO,
-            health: Health::new(Hp::new(2.0)),
-            held_items: HeldItems {
-                left_hand: None,
-                right_hand: Some(Item::sword(Hp::new(1.0))),
-            },
-        });
+    /// Swaps the item in the player's left hand with the stashed one.
+    pub fn swap_player_item(&mut self) {
+        let items = self
+            .units
+            .held_items
+            .get_mut(self.player.unit)
+            .expect("Player not found");
+        std::mem::swap(
+            items.get_hand_mut(HandId::LeftHand),
+            &mut self.player.stashed_item,
+        );
+    }
+
+    /// Plays the sound effect of the gameplay event.
+    pub fn play_sfx(&mut self, event: SfxEvent) {
+        let position = self.music_controller.schedule_position(0.0);
+        self.sfx_controller.play(event, position);
     }
 }
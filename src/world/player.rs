@@ -5,10 +5,12 @@ pub type PlayerAction = Action;
 #[derive(Debug)]
 pub struct Player {
     pub unit: UnitId,
+    /// Item carried in the bag, swapped with the one in the left hand.
+    pub stashed_item: Option<Item>,
 }
 
 impl Player {
-    pub fn new(unit: UnitId) -> Self {
-        Self { unit }
+    pub fn new(unit: UnitId, stashed_item: Option<Item>) -> Self {
+        Self { unit, stashed_item }
     }
 }
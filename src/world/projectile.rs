@@ -12,7 +12,8 @@ pub struct Projectile {
     pub on_contact: Effect,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ProjectilePrefab {
     pub target_filter: FractionFilter,
     pub on_contact: Effect,
@@ -0,0 +1,236 @@
+use super::*;
+
+/// Spawns waves of enemies from the level's spawn points.
+pub struct Spawner {
+    config: SpawnerConfig,
+    /// The waves of the config with the fractions and units resolved.
+    waves: Vec<Wave>,
+    /// Index of the current wave.
+    wave: usize,
+    state: SpawnerState,
+}
+
+#[derive(Debug, Clone, Deserialize, geng::Load)]
+#[load(json)]
+#[serde(deny_unknown_fields)]
+pub struct SpawnerConfig {
+    pub spawn_points: Vec<vec2<Coord>>,
+    pub waves: Vec<WaveConfig>,
+    pub scaling: WaveScaling,
+    /// Start over from the first wave after the last one is cleared.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveConfig {
+    /// Time to wait before the wave, counted from the previous wave being cleared.
+    pub delay: WaveDelay,
+    pub fraction: FractionName,
+    /// Base number of each of the units in the wave, referenced by the prefab name.
+    pub units: Vec<(usize, String)>,
+}
+
+/// A [`WaveConfig`] with the fraction and the unit prefabs resolved.
+#[derive(Debug, Clone)]
+pub struct Wave {
+    pub delay: WaveDelay,
+    pub fraction: Fraction,
+    pub units: Vec<(usize, UnitPrefab)>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveDelay {
+    Beats(Ticks),
+    Bars(Ticks),
+}
+
+/// Multiplies the size of the waves as the game gets harder.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveScaling {
+    /// BPM at which the waves have their base size.
+    pub base_bpm: f32,
+    /// Additional wave size per point of the player's score, relative to the base size.
+    pub per_score: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveEvent {
+    /// The wave with the index has started spawning.
+    Started(usize),
+    /// All units of the wave with the index are dead.
+    Cleared(usize),
+}
+
+pub type SpawnerResult<T> = Result<T, SpawnerError>;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum SpawnerError {
+    #[error("Unknown fraction {0:?}")]
+    UnknownFraction(FractionName),
+    #[error("Unknown unit prefab {0:?}")]
+    UnknownUnit(String),
+    #[error("Base bpm of the wave scaling must be positive, got {0}")]
+    NonPositiveBaseBpm(f32),
+    #[error("No spawn points for the waves")]
+    NoSpawnPoints,
+}
+
+#[derive(Debug)]
+enum SpawnerState {
+    /// Waiting for the delay of the wave, counting the music ticks.
+    Waiting { ticks: Ticks },
+    Active {
+        /// Units waiting for a free spawn point.
+        pending: Vec<UnitPrefab>,
+        /// Spawned units.
+        alive: Vec<UnitId>,
+    },
+    /// All waves are cleared.
+    Finished,
+}
+
+impl Spawner {
+    pub fn new(
+        config: SpawnerConfig,
+        fractions: &Fractions,
+        prefabs: &UnitPrefabs,
+    ) -> SpawnerResult<Self> {
+        if config.scaling.base_bpm <= 0.0 {
+            return Err(SpawnerError::NonPositiveBaseBpm(config.scaling.base_bpm));
+        }
+        if config.spawn_points.is_empty() && !config.waves.is_empty() {
+            return Err(SpawnerError::NoSpawnPoints);
+        }
+
+        let mut waves = Vec::new();
+        for wave in &config.waves {
+            let fraction = fractions
+                .find(&wave.fraction)
+                .ok_or_else(|| SpawnerError::UnknownFraction(wave.fraction.clone()))?;
+            let mut units = Vec::new();
+            for (count, name) in &wave.units {
+                let prefab = prefabs
+                    .get(name)
+                    .ok_or_else(|| SpawnerError::UnknownUnit(name.clone()))?;
+                units.push((*count, prefab.clone()));
+            }
+            waves.push(Wave {
+                delay: wave.delay,
+                fraction,
+                units,
+            });
+        }
+
+        let state = if waves.is_empty() {
+            SpawnerState::Finished
+        } else {
+            SpawnerState::Waiting { ticks: 0 }
+        };
+        Ok(Self {
+            config,
+            waves,
+            wave: 0,
+            state,
+        })
+    }
+
+    /// Index of the current wave.
+    pub fn wave(&self) -> usize {
+        self.wave
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, SpawnerState::Finished)
+    }
+
+    /// Advances the spawner by one music tick.
+    pub fn tick(
+        &mut self,
+        units: &mut StructOf<Collection<Unit>>,
+        grid: &Grid,
+        beat_controller: &BeatController,
+        score: u64,
+    ) -> Option<WaveEvent> {
+        let wave = self.waves.get(self.wave)?;
+        match &mut self.state {
+            SpawnerState::Waiting { ticks } => {
+                *ticks += 1;
+                if *ticks < wave.delay.to_ticks(beat_controller) {
+                    return None;
+                }
+                let scale = self.config.scaling.scale(beat_controller.get_bpm(), score);
+                let pending = wave
+                    .units
+                    .iter()
+                    .flat_map(|(count, prefab)| {
+                        // Scaling never removes a unit from the wave entirely
+                        let count = ((*count as f32 * scale).round() as usize).max(1);
+                        std::iter::repeat(prefab).take(count).cloned()
+                    })
+                    .collect();
+                self.state = SpawnerState::Active {
+                    pending,
+                    alive: Vec::new(),
+                };
+                Some(WaveEvent::Started(self.wave))
+            }
+            SpawnerState::Active { pending, alive } => {
+                for &point in &self.config.spawn_points {
+                    let Some(prefab) = pending.last() else {
+                        break;
+                    };
+                    if !prefab.fits(units, point) {
+                        // Occupied
+                        continue;
+                    }
+                    let prefab = pending.pop().expect("Checked above");
+                    let unit = prefab.instantiate(UnitInst {
+                        grid_position: point,
+                        world_position: grid.grid_to_world(point),
+                        fraction: wave.fraction,
+                        leader: None,
+                    });
+                    alive.push(units.insert(unit));
+                }
+
+                alive.retain(|&id| units.health.get(id).is_some());
+                if !pending.is_empty() || !alive.is_empty() {
+                    return None;
+                }
+
+                let cleared = self.wave;
+                self.wave += 1;
+                if self.wave >= self.waves.len() && self.config.repeat {
+                    self.wave = 0;
+                }
+                self.state = if self.wave < self.waves.len() {
+                    SpawnerState::Waiting { ticks: 0 }
+                } else {
+                    SpawnerState::Finished
+                };
+                Some(WaveEvent::Cleared(cleared))
+            }
+            SpawnerState::Finished => None,
+        }
+    }
+}
+
+impl WaveDelay {
+    pub fn to_ticks(self, beat_controller: &BeatController) -> Ticks {
+        match self {
+            WaveDelay::Beats(beats) => beats * beat_controller.ticks_per_beat(),
+            WaveDelay::Bars(bars) => bars * beat_controller.ticks_per_bar(),
+        }
+    }
+}
+
+impl WaveScaling {
+    /// Returns the multiplier of the wave size.
+    pub fn scale(&self, bpm: f32, score: u64) -> f32 {
+        bpm / self.base_bpm * (1.0 + self.per_score * score as f32)
+    }
+}
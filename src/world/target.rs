@@ -1,61 +1,134 @@
 use super::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TargetSelector {
     pub filter: TargetFilter,
     pub fitness: TargetFitness,
+    /// How to choose between the targets with the same fitness.
+    pub tie_break: TieBreak,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TargetFilter {
     Own,
     Fraction(FractionFilter),
+    /// Unoccupied cells within `radius` (king distance) of the unit.
+    EmptyCell {
+        radius: Coord,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FractionFilter {
     Any,
     Ally,
+    Neutral,
+    /// Hostile fractions.
     Enemy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TargetFitness {
     Negative(Box<TargetFitness>),
+    /// King distance between the closest cells of the footprints:
+    /// diagonal steps count as one.
     Distance,
+    /// Manhattan distance between the closest cells of the footprints:
+    /// diagonal steps count as two.
+    ManhattanDistance,
+    /// Current health of the target unit.
+    Health,
+    MaxHealth,
+    /// Damage dealt by the target unit recently.
+    Threat,
+    /// One for the player, zero otherwise.
+    IsPlayer,
+    /// Weighted sum of the fitnesses.
+    Sum(Vec<(R32, TargetFitness)>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Keep the first target found.
+    First,
+    /// Pick any of the tied targets at random.
+    Random,
+    /// Maximize another fitness among the tied targets.
+    Fitness(Box<TargetFitness>),
 }
 
 pub type Fitness = R32;
 
 impl TargetSelector {
     pub fn evaluate(&self, world: &World, unit: UnitId) -> ComponentResult<Option<EffectTarget>> {
-        let mut best_target = None;
-
-        let mut eval_unit = |id: UnitId| -> ComponentResult<()> {
-            let fitness = self.fitness.evaluate(id, world, unit)?;
-            let target = (id, fitness);
-            best_target = Some(
-                best_target
-                    .map(|best| std::cmp::max_by_key(best, target, |&(_, fit)| fit))
-                    .unwrap_or(target),
-            );
-            Ok(())
-        };
+        let mut candidates = Vec::new();
+        match self.filter {
+            TargetFilter::Own => candidates.push(EffectTarget::Unit(unit)),
+            TargetFilter::Fraction(_) => {
+                for id in world.units.ids() {
+                    if id != unit && self.filter.check(id, world, unit)? {
+                        candidates.push(EffectTarget::Unit(id));
+                    }
+                }
+            }
+            TargetFilter::EmptyCell { radius } => {
+                let &pos = world.units.grid_position.get(unit).expect("Unit not found");
+                for x in -radius..=radius {
+                    for y in -radius..=radius {
+                        let cell = pos + vec2(x, y);
+                        if world.get_unit_at(cell).is_err() {
+                            candidates.push(EffectTarget::Position(cell));
+                        }
+                    }
+                }
+            }
+        }
 
-        if let TargetFilter::Own = self.filter {
-            eval_unit(unit)?;
-        } else {
-            for id in world.units.ids() {
-                if id != unit
-                    // && (id == world.player.unit || world.units.unit.contains(id))
-                    && self.filter.check(id, world, unit)?
-                {
-                    eval_unit(id)?;
+        let mut best = Vec::new();
+        let mut best_fitness = None;
+        for target in candidates {
+            let fitness = self.fitness.evaluate(target, world, unit)?;
+            match best_fitness {
+                Some(best_fit) if fitness < best_fit => continue,
+                Some(best_fit) if fitness == best_fit => {}
+                _ => {
+                    best.clear();
+                    best_fitness = Some(fitness);
                 }
             }
+            best.push(target);
         }
 
-        Ok(best_target.map(|(target, _)| EffectTarget::Unit(target)))
+        self.tie_break.choose(best, world, unit)
+    }
+}
+
+impl TieBreak {
+    fn choose(
+        &self,
+        targets: Vec<EffectTarget>,
+        world: &World,
+        unit: UnitId,
+    ) -> ComponentResult<Option<EffectTarget>> {
+        match self {
+            TieBreak::First => Ok(targets.into_iter().next()),
+            TieBreak::Random => Ok(targets.choose(&mut thread_rng()).copied()),
+            TieBreak::Fitness(fitness) => {
+                let mut best_target = None;
+                for target in targets {
+                    let fit = fitness.evaluate(target, world, unit)?;
+                    if best_target.map_or(true, |(_, best_fit)| fit > best_fit) {
+                        best_target = Some((target, fit));
+                    }
+                }
+                Ok(best_target.map(|(target, _)| target))
+            }
+        }
     }
 }
 
@@ -64,6 +137,7 @@ impl TargetFilter {
         match self {
             TargetFilter::Own => Ok(target == unit),
             TargetFilter::Fraction(filter) => filter.check_query(target, world, unit),
+            TargetFilter::EmptyCell { .. } => Ok(false),
         }
     }
 }
@@ -72,14 +146,16 @@ impl FractionFilter {
     pub fn check_query(self, target: UnitId, world: &World, unit: UnitId) -> ComponentResult<bool> {
         let &unit = world.units.fraction.get(unit).expect("Unit not found");
         let &target = world.units.fraction.get(target).expect("Unit not found");
-        Ok(self.check(unit, target))
+        Ok(self.check(&world.fractions, unit, target))
     }
 
-    pub fn check(self, unit: Fraction, other: Fraction) -> bool {
+    pub fn check(self, fractions: &Fractions, unit: Fraction, other: Fraction) -> bool {
+        let relation = fractions.relation(unit, other);
         match self {
             FractionFilter::Any => true,
-            FractionFilter::Ally => unit == other,
-            FractionFilter::Enemy => unit != other,
+            FractionFilter::Ally => relation == Relation::Ally,
+            FractionFilter::Neutral => relation == Relation::Neutral,
+            FractionFilter::Enemy => relation == Relation::Hostile,
         }
     }
 }
@@ -87,22 +163,56 @@ impl FractionFilter {
 impl TargetFitness {
     pub fn evaluate(
         &self,
-        target: UnitId,
+        target: EffectTarget,
         world: &World,
         unit: UnitId,
     ) -> ComponentResult<Fitness> {
+        // Unit metrics of an empty position are zero
+        let target_unit = target.find_unit(world).ok();
+
         match self {
             TargetFitness::Negative(fitness) => Ok(-fitness.evaluate(target, world, unit)?),
             TargetFitness::Distance => {
-                let &pos = world.units.grid_position.get(unit).expect("Unit not found");
-                let &target_pos = world
+                let distance = world.distance_to(unit, target)?;
+                Ok(Fitness::new(distance as f32))
+            }
+            TargetFitness::ManhattanDistance => {
+                let distance = world.manhattan_distance_to(unit, target)?;
+                Ok(Fitness::new(distance as f32))
+            }
+            TargetFitness::Health => Ok(target_unit.map_or(Fitness::ZERO, |target| {
+                world
                     .units
-                    .grid_position
+                    .health
                     .get(target)
-                    .expect("Unit not found");
-                let delta = target_pos - pos;
-                let distance = crate::util::king_distance(delta);
-                Ok(Fitness::new(distance as f32))
+                    .expect("Unit not found")
+                    .get()
+            })),
+            TargetFitness::MaxHealth => Ok(target_unit.map_or(Fitness::ZERO, |target| {
+                world
+                    .units
+                    .health
+                    .get(target)
+                    .expect("Unit not found")
+                    .get_max()
+            })),
+            TargetFitness::Threat => Ok(target_unit.map_or(Fitness::ZERO, |target| {
+                *world.units.threat.get(target).expect("Unit not found")
+            })),
+            TargetFitness::IsPlayer => {
+                let is_player = target_unit == Some(world.player.unit);
+                Ok(if is_player {
+                    Fitness::ONE
+                } else {
+                    Fitness::ZERO
+                })
+            }
+            TargetFitness::Sum(fitnesses) => {
+                let mut sum = Fitness::ZERO;
+                for (weight, fitness) in fitnesses {
+                    sum += *weight * fitness.evaluate(target, world, unit)?;
+                }
+                Ok(sum)
             }
         }
     }
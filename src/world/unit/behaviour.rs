@@ -10,6 +10,8 @@ pub enum BehaviourError {
     Component(#[from] ComponentError),
     #[error("Context error: {0}")]
     Context(#[from] BehaviourContextError),
+    #[error("Behaviour {0:?} was not resolved when loading")]
+    Unresolved(BehaviourName),
 }
 
 #[derive(thiserror::Error, Debug, Clone)]
@@ -20,8 +22,11 @@ pub enum BehaviourContextError {
     NoInput,
 }
 
+pub type BehaviourName = String;
+
 /// Basically the decision tree of the unit.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum UnitBehaviour {
     Act(UnitAction),
     SelectTarget {
@@ -37,25 +42,127 @@ pub enum UnitBehaviour {
         then_behave: Box<UnitBehaviour>,
         else_behave: Box<UnitBehaviour>,
     },
+    /// Sets the input for the `Act` nodes.
+    Aim {
+        aim: BehaviourAim,
+        then_behave: Box<UnitBehaviour>,
+    },
+    /// Behaves as each of the children in order, one per beat.
+    /// Fails as soon as any of the children fails.
+    Sequence(Vec<UnitBehaviour>),
+    /// Behaves as the first child that does not fail.
+    Selector(Vec<UnitBehaviour>),
+    /// Behaves as a random child, chosen with the given weights.
+    RandomChoice(Vec<(R32, UnitBehaviour)>),
+    /// Behaves as the child until it succeeds `count` times.
+    Repeat {
+        count: Ticks,
+        behaviour: Box<UnitBehaviour>,
+    },
+    /// Moves towards the unit's leader when farther than `distance` cells from it,
+    /// behaves as the child otherwise or if the unit has no leader.
+    Leash {
+        distance: Coord,
+        behaviour: Box<UnitBehaviour>,
+    },
+    /// Fails for `beats` of the unit's beats after the child succeeds.
+    Cooldown {
+        beats: Ticks,
+        behaviour: Box<UnitBehaviour>,
+    },
+    /// A behaviour shared between the units, substituted when loading `units.json`.
+    Named(BehaviourName),
+}
+
+/// Memory of the unit's decision making, persisted between beats.
+#[derive(Debug, Clone, Default)]
+pub struct Blackboard {
+    /// Number of decisions the unit has made.
+    pub beat: Ticks,
+    /// State of the stateful nodes, identified by their path in the tree:
+    /// the current child of a `Sequence`, the number of successes of a `Repeat`,
+    /// or the beat of the last success of a `Cooldown`.
+    nodes: HashMap<Vec<usize>, Ticks>,
 }
 
+/// Result of evaluating a behaviour node.
 #[derive(Debug, Clone)]
+enum BehaviourStatus {
+    /// No action is taken.
+    Failure,
+    /// The action is taken and the node continues on the next beat.
+    Running(UnitAction, ActionInput),
+    /// The action is taken and the node is finished.
+    Success(UnitAction, ActionInput),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BehaviourCondition {
-    TargetInRange { distance: Coord },
+    TargetInRange {
+        distance: Coord,
+    },
+    /// Own health ratio (in range 0..1) is at most `ratio`.
+    HealthBelow {
+        ratio: R32,
+    },
+    /// Target's health ratio (in range 0..1) is at most `ratio`.
+    TargetHealthBelow {
+        ratio: R32,
+    },
+    /// No other units stand between the unit and the target.
+    LineOfSight,
+    /// At least `count` units of the fraction are within `radius` cells.
+    UnitsInRadius {
+        filter: FractionFilter,
+        radius: Coord,
+        count: usize,
+    },
+    ItemAvailable {
+        item: ItemId,
+    },
+    /// The current beat of the `UnitBeat::Synchronized` unit is `beat`.
+    SynchronizedBeat {
+        beat: Ticks,
+    },
+    And(Vec<BehaviourCondition>),
+    Or(Vec<BehaviourCondition>),
+    Not(Box<BehaviourCondition>),
 }
 
-#[derive(Debug, Clone)]
-enum BehaviourContext {
-    None,
-    Target(EffectTarget),
-    Input(ActionInput),
+/// Where an `UnitBehaviour::Aim` node aims the action.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviourAim {
+    /// At the selected target.
+    Target,
+    /// At the cell with the given offset from the selected target.
+    TargetOffset(vec2<Coord>),
+    /// At the cell with the given offset from the unit itself.
+    SelfOffset(vec2<Coord>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct BehaviourContext {
+    target: Option<EffectTarget>,
+    input: Option<ActionInput>,
 }
 
 impl UnitBeat {
-    pub fn calc_bpm(&self, player_bpm: f32) -> f32 {
+    pub fn calc_bpm(&self, beat_controller: &BeatController) -> f32 {
+        let player_bpm = beat_controller.get_bpm();
         match *self {
             UnitBeat::Synchronized { unit, player, .. } => player_bpm * unit as f32 / player as f32,
             UnitBeat::Independent { bpm } => bpm as f32,
+            UnitBeat::Bars { bars, .. } => {
+                player_bpm / (bars * beat_controller.beats_per_bar()).max(1) as f32
+            }
+            UnitBeat::MusicTicks {
+                cycle, ref ticks, ..
+            } => {
+                let ticks_per_beat = beat_controller.ticks_per_beat();
+                player_bpm * (ticks.len() as Ticks * ticks_per_beat) as f32 / cycle.max(1) as f32
+            }
         }
     }
 }
@@ -65,19 +172,34 @@ impl UnitBehaviour {
         &self,
         world: &World,
         unit: UnitId,
+        blackboard: &mut Blackboard,
     ) -> BehaviourResult<Option<(UnitAction, ActionInput)>> {
-        let context = BehaviourContext::None;
-        self.evaluate_with_context(world, unit, context)
+        blackboard.beat += 1;
+        let context = BehaviourContext::default();
+        let status =
+            self.evaluate_with_context(world, unit, context, blackboard, &mut Vec::new())?;
+        Ok(match status {
+            BehaviourStatus::Failure => None,
+            BehaviourStatus::Running(action, input) | BehaviourStatus::Success(action, input) => {
+                Some((action, input))
+            }
+        })
     }
 
+    /// `path` is the position of the node in the tree, used to store its state.
     fn evaluate_with_context(
         &self,
         world: &World,
         unit: UnitId,
         context: BehaviourContext,
-    ) -> BehaviourResult<Option<(UnitAction, ActionInput)>> {
+        blackboard: &mut Blackboard,
+        path: &mut Vec<usize>,
+    ) -> BehaviourResult<BehaviourStatus> {
         match self {
-            Self::Act(action) => Ok(Some((action.clone(), context.expect_input()?))),
+            Self::Act(action) => Ok(BehaviourStatus::Success(
+                action.clone(),
+                context.expect_input()?,
+            )),
             Self::SelectTarget {
                 selector,
                 then_behave,
@@ -85,11 +207,14 @@ impl UnitBehaviour {
                 match selector.evaluate(world, unit)? {
                     None => {
                         // No target found
-                        Ok(None)
+                        Ok(BehaviourStatus::Failure)
                     }
                     Some(target) => {
-                        let context = BehaviourContext::Target(target);
-                        then_behave.evaluate_with_context(world, unit, context)
+                        let context = BehaviourContext {
+                            target: Some(target),
+                            ..context
+                        };
+                        then_behave.evaluate_child(world, unit, context, blackboard, path, 0)
                     }
                 }
             }
@@ -99,34 +224,286 @@ impl UnitBehaviour {
                 let &pos = world.units.grid_position.get(unit).expect("Unit not found");
                 let delta = target_pos - pos;
                 let move_delta = crate::util::vec_to_dir(delta.map(|x| x as f32));
-                Ok(Some((
+                Ok(BehaviourStatus::Success(
                     Action::Move(ActionMove::Slide(MoveSlide { delta: move_delta })),
                     ActionInput { target },
-                )))
+                ))
             }
             &Self::UseItemOnTarget { item } => {
                 let target = context.expect_target()?;
-                Ok(Some((
+                Ok(BehaviourStatus::Success(
                     Action::UseItem(ActionUseItem { item }),
                     ActionInput { target },
-                )))
+                ))
             }
             Self::If {
                 condition,
                 then_behave,
                 else_behave,
             } => {
-                let behave = if condition.evaluate(world, unit, &context)? {
-                    then_behave
+                let (index, behave) = if condition.evaluate(world, unit, &context)? {
+                    (0, then_behave)
                 } else {
-                    else_behave
+                    (1, else_behave)
+                };
+                behave.evaluate_child(world, unit, context, blackboard, path, index)
+            }
+            Self::Aim { aim, then_behave } => {
+                let target = match *aim {
+                    BehaviourAim::Target => context.target()?,
+                    BehaviourAim::TargetOffset(offset) => {
+                        EffectTarget::Position(context.target()?.find_pos(world)? + offset)
+                    }
+                    BehaviourAim::SelfOffset(offset) => {
+                        let &pos = world.units.grid_position.get(unit).expect("Unit not found");
+                        EffectTarget::Position(pos + offset)
+                    }
                 };
-                behave.evaluate_with_context(world, unit, context)
+                let context = BehaviourContext {
+                    input: Some(ActionInput { target }),
+                    ..context
+                };
+                then_behave.evaluate_child(world, unit, context, blackboard, path, 0)
+            }
+            Self::Sequence(children) => sequence_node(
+                children.len(),
+                blackboard,
+                path,
+                |current, blackboard, path| {
+                    children[current]
+                        .evaluate_child(world, unit, context, blackboard, path, current)
+                },
+            ),
+            Self::Selector(children) => {
+                for (index, child) in children.iter().enumerate() {
+                    let status = child.evaluate_child(
+                        world,
+                        unit,
+                        context.clone(),
+                        blackboard,
+                        path,
+                        index,
+                    )?;
+                    if !matches!(status, BehaviourStatus::Failure) {
+                        return Ok(status);
+                    }
+                }
+                Ok(BehaviourStatus::Failure)
+            }
+            Self::RandomChoice(options) => {
+                let Ok((index, (_, child))) = options
+                    .iter()
+                    .enumerate()
+                    .collect::<Vec<_>>()
+                    .choose_weighted(&mut thread_rng(), |(_, (weight, _))| weight.as_f32())
+                    .copied()
+                else {
+                    // No options or invalid weights
+                    return Ok(BehaviourStatus::Failure);
+                };
+                child.evaluate_child(world, unit, context, blackboard, path, index)
+            }
+            Self::Repeat { count, behaviour } => {
+                repeat_node(*count, blackboard, path, |blackboard, path| {
+                    behaviour.evaluate_child(world, unit, context, blackboard, path, 0)
+                })
+            }
+            Self::Leash {
+                distance,
+                behaviour,
+            } => {
+                let &pos = world.units.grid_position.get(unit).expect("Unit not found");
+                let leader_pos = world
+                    .units
+                    .unit
+                    .get(unit)
+                    .expect("Unit not found")
+                    .as_ref()
+                    .and_then(|ai| ai.leader)
+                    .and_then(|leader| {
+                        // The leader might have died
+                        let &leader_pos = world.units.grid_position.get(leader)?;
+                        Some((leader, leader_pos))
+                    });
+                match leader_pos {
+                    Some((leader, leader_pos))
+                        if crate::util::king_distance(leader_pos - pos) > *distance =>
+                    {
+                        let context = BehaviourContext {
+                            target: Some(EffectTarget::Unit(leader)),
+                            ..context
+                        };
+                        Self::MoveToTarget
+                            .evaluate_with_context(world, unit, context, blackboard, path)
+                    }
+                    _ => behaviour.evaluate_child(world, unit, context, blackboard, path, 0),
+                }
+            }
+            Self::Cooldown { beats, behaviour } => {
+                cooldown_node(*beats, blackboard, path, |blackboard, path| {
+                    behaviour.evaluate_child(world, unit, context, blackboard, path, 0)
+                })
+            }
+            Self::Named(name) => Err(BehaviourError::Unresolved(name.clone())),
+        }
+    }
+
+    /// Evaluates the node as the `index`th child of the node at `path`.
+    fn evaluate_child(
+        &self,
+        world: &World,
+        unit: UnitId,
+        context: BehaviourContext,
+        blackboard: &mut Blackboard,
+        path: &mut Vec<usize>,
+        index: usize,
+    ) -> BehaviourResult<BehaviourStatus> {
+        path.push(index);
+        let status = self.evaluate_with_context(world, unit, context, blackboard, path);
+        path.pop();
+        status
+    }
+
+    /// Substitutes the `Named` nodes with the `behaviours`.
+    pub fn resolve(
+        &mut self,
+        behaviours: &HashMap<BehaviourName, UnitBehaviour>,
+    ) -> Result<(), String> {
+        self.resolve_with(behaviours, &mut Vec::new())
+    }
+
+    /// `names` are the named behaviours being resolved, used to detect recursion.
+    fn resolve_with(
+        &mut self,
+        behaviours: &HashMap<BehaviourName, UnitBehaviour>,
+        names: &mut Vec<BehaviourName>,
+    ) -> Result<(), String> {
+        match self {
+            Self::Named(name) => {
+                let name = name.clone();
+                if names.contains(&name) {
+                    return Err(format!("Behaviour {name:?} refers to itself"));
+                }
+                let mut behaviour = behaviours
+                    .get(&name)
+                    .ok_or_else(|| format!("Unknown behaviour {name:?}"))?
+                    .clone();
+                names.push(name);
+                behaviour.resolve_with(behaviours, names)?;
+                names.pop();
+                *self = behaviour;
+            }
+            Self::Act(_) | Self::MoveToTarget | Self::UseItemOnTarget { .. } => {}
+            Self::SelectTarget { then_behave, .. } | Self::Aim { then_behave, .. } => {
+                then_behave.resolve_with(behaviours, names)?;
+            }
+            Self::If {
+                then_behave,
+                else_behave,
+                ..
+            } => {
+                then_behave.resolve_with(behaviours, names)?;
+                else_behave.resolve_with(behaviours, names)?;
+            }
+            Self::Sequence(children) | Self::Selector(children) => {
+                for child in children {
+                    child.resolve_with(behaviours, names)?;
+                }
+            }
+            Self::RandomChoice(options) => {
+                for (_, child) in options {
+                    child.resolve_with(behaviours, names)?;
+                }
+            }
+            Self::Repeat { behaviour, .. }
+            | Self::Leash { behaviour, .. }
+            | Self::Cooldown { behaviour, .. } => {
+                behaviour.resolve_with(behaviours, names)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Behaves as the current child of a `Sequence` node with `len` children,
+/// evaluated by `child` given its index.
+fn sequence_node(
+    len: usize,
+    blackboard: &mut Blackboard,
+    path: &mut Vec<usize>,
+    child: impl FnOnce(usize, &mut Blackboard, &mut Vec<usize>) -> BehaviourResult<BehaviourStatus>,
+) -> BehaviourResult<BehaviourStatus> {
+    let current = blackboard.nodes.get(path).copied().unwrap_or(0) as usize;
+    if current >= len {
+        blackboard.nodes.remove(path);
+        return Ok(BehaviourStatus::Failure);
+    }
+    match child(current, blackboard, path)? {
+        BehaviourStatus::Failure => {
+            // Start over
+            blackboard.nodes.remove(path);
+            Ok(BehaviourStatus::Failure)
+        }
+        BehaviourStatus::Running(action, input) => Ok(BehaviourStatus::Running(action, input)),
+        BehaviourStatus::Success(action, input) => {
+            let next = current + 1;
+            if next >= len {
+                blackboard.nodes.remove(path);
+                Ok(BehaviourStatus::Success(action, input))
+            } else {
+                blackboard.nodes.insert(path.clone(), next as Ticks);
+                Ok(BehaviourStatus::Running(action, input))
             }
         }
     }
 }
 
+/// Behaves as the child of a `Repeat` node until it succeeds `count` times.
+fn repeat_node(
+    count: Ticks,
+    blackboard: &mut Blackboard,
+    path: &mut Vec<usize>,
+    child: impl FnOnce(&mut Blackboard, &mut Vec<usize>) -> BehaviourResult<BehaviourStatus>,
+) -> BehaviourResult<BehaviourStatus> {
+    match child(blackboard, path)? {
+        BehaviourStatus::Failure => {
+            blackboard.nodes.remove(path);
+            Ok(BehaviourStatus::Failure)
+        }
+        BehaviourStatus::Running(action, input) => Ok(BehaviourStatus::Running(action, input)),
+        BehaviourStatus::Success(action, input) => {
+            let done = blackboard.nodes.get(path).copied().unwrap_or(0) + 1;
+            if done >= count {
+                blackboard.nodes.remove(path);
+                Ok(BehaviourStatus::Success(action, input))
+            } else {
+                blackboard.nodes.insert(path.clone(), done);
+                Ok(BehaviourStatus::Running(action, input))
+            }
+        }
+    }
+}
+
+/// Behaves as the child of a `Cooldown` node unless it has succeeded
+/// in the last `beats` beats.
+fn cooldown_node(
+    beats: Ticks,
+    blackboard: &mut Blackboard,
+    path: &mut Vec<usize>,
+    child: impl FnOnce(&mut Blackboard, &mut Vec<usize>) -> BehaviourResult<BehaviourStatus>,
+) -> BehaviourResult<BehaviourStatus> {
+    if let Some(&last_used) = blackboard.nodes.get(path) {
+        if blackboard.beat <= last_used + beats {
+            return Ok(BehaviourStatus::Failure);
+        }
+    }
+    let status = child(blackboard, path)?;
+    if let BehaviourStatus::Success(..) = status {
+        blackboard.nodes.insert(path.clone(), blackboard.beat);
+    }
+    Ok(status)
+}
+
 impl BehaviourCondition {
     fn evaluate(
         &self,
@@ -134,31 +511,186 @@ impl BehaviourCondition {
         unit: UnitId,
         context: &BehaviourContext,
     ) -> BehaviourResult<bool> {
+        let &pos = world.units.grid_position.get(unit).expect("Unit not found");
         match self {
-            &BehaviourCondition::TargetInRange { distance } => match context {
-                BehaviourContext::Target(target) => {
-                    let &pos = world.units.grid_position.get(unit).expect("Unit not found");
-                    let target_pos = target.find_pos(world)?;
-                    Ok(crate::util::king_distance(target_pos - pos) <= distance)
+            &BehaviourCondition::TargetInRange { distance } => {
+                Ok(world.distance_to(unit, context.target()?)? <= distance)
+            }
+            &BehaviourCondition::HealthBelow { ratio } => {
+                let health = world.units.health.get(unit).expect("Unit not found");
+                Ok(health.get_ratio() <= ratio)
+            }
+            &BehaviourCondition::TargetHealthBelow { ratio } => {
+                let Ok(target) = context.target()?.find_unit(world) else {
+                    // Target is not a unit
+                    return Ok(false);
+                };
+                let health = world.units.health.get(target).expect("Unit not found");
+                Ok(health.get_ratio() <= ratio)
+            }
+            BehaviourCondition::LineOfSight => {
+                let target = context.target()?;
+                let target_pos = target.find_pos(world)?;
+                let target_unit = target.find_unit(world).ok();
+                // Only other units block the line, not the footprints of the two
+                let blocked = crate::util::grid_line(pos, target_pos)
+                    .into_iter()
+                    .filter_map(|cell| world.get_unit_at(cell).ok())
+                    .any(|other| other != unit && Some(other) != target_unit);
+                Ok(!blocked)
+            }
+            &BehaviourCondition::UnitsInRadius {
+                filter,
+                radius,
+                count,
+            } => {
+                let mut found = 0;
+                for id in world.units.ids() {
+                    if id != unit
+                        && world.distance_to(unit, EffectTarget::Unit(id))? <= radius
+                        && filter.check_query(id, world, unit)?
+                    {
+                        found += 1;
+                    }
                 }
-                _ => Err(BehaviourError::Context(BehaviourContextError::NoTarget)),
-            },
+                Ok(found >= count)
+            }
+            &BehaviourCondition::ItemAvailable { item } => {
+                let items = world.units.held_items.get(unit).expect("Unit not found");
+                Ok(items.get_item(item).is_some())
+            }
+            &BehaviourCondition::SynchronizedBeat { beat } => {
+                let ai = world.units.unit.get(unit).expect("Unit not found");
+                match ai {
+                    Some(UnitAI {
+                        beat: UnitBeat::Synchronized { current_beat, .. },
+                        ..
+                    }) => Ok(*current_beat == beat),
+                    _ => Ok(false),
+                }
+            }
+            BehaviourCondition::And(conditions) => {
+                for condition in conditions {
+                    if !condition.evaluate(world, unit, context)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            BehaviourCondition::Or(conditions) => {
+                for condition in conditions {
+                    if condition.evaluate(world, unit, context)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            BehaviourCondition::Not(condition) => Ok(!condition.evaluate(world, unit, context)?),
         }
     }
 }
 
 impl BehaviourContext {
+    fn target(&self) -> Result<EffectTarget, BehaviourContextError> {
+        self.target.ok_or(BehaviourContextError::NoTarget)
+    }
+
     pub fn expect_target(self) -> Result<EffectTarget, BehaviourContextError> {
-        match self {
-            Self::Target(target) => Ok(target),
-            _ => Err(BehaviourContextError::NoTarget),
-        }
+        self.target()
     }
 
     pub fn expect_input(self) -> Result<ActionInput, BehaviourContextError> {
-        match self {
-            Self::Input(input) => Ok(input),
-            _ => Err(BehaviourContextError::NoInput),
-        }
+        self.input.ok_or(BehaviourContextError::NoInput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn success() -> BehaviourResult<BehaviourStatus> {
+        let action = UnitAction::Move(ActionMove::Slide(MoveSlide { delta: vec2::ZERO }));
+        let input = ActionInput {
+            target: EffectTarget::Position(vec2::ZERO),
+        };
+        Ok(BehaviourStatus::Success(action, input))
+    }
+
+    fn failure() -> BehaviourResult<BehaviourStatus> {
+        Ok(BehaviourStatus::Failure)
+    }
+
+    #[test]
+    fn sequence_advances_and_resets() {
+        let mut blackboard = Blackboard::default();
+        let mut path = vec![0];
+        let mut step = |result: fn() -> BehaviourResult<BehaviourStatus>| {
+            let mut evaluated = None;
+            let status = sequence_node(3, &mut blackboard, &mut path, |current, _, _| {
+                evaluated = Some(current);
+                result()
+            })
+            .unwrap();
+            (evaluated.unwrap(), status)
+        };
+
+        assert!(matches!(step(success), (0, BehaviourStatus::Running(..))));
+        assert!(matches!(step(success), (1, BehaviourStatus::Running(..))));
+        assert!(matches!(step(success), (2, BehaviourStatus::Success(..))));
+        // Starts over after the last child
+        assert!(matches!(step(success), (0, BehaviourStatus::Running(..))));
+        assert!(matches!(step(failure), (1, BehaviourStatus::Failure)));
+        // Starts over after a failure
+        assert!(matches!(step(success), (0, BehaviourStatus::Running(..))));
+    }
+
+    #[test]
+    fn repeat_counts_successes() {
+        let mut blackboard = Blackboard::default();
+        let mut path = vec![0];
+        let mut step = |result: fn() -> BehaviourResult<BehaviourStatus>| {
+            repeat_node(2, &mut blackboard, &mut path, |_, _| result()).unwrap()
+        };
+
+        assert!(matches!(step(success), BehaviourStatus::Running(..)));
+        assert!(matches!(step(success), BehaviourStatus::Success(..)));
+        // Starts over after the last repetition
+        assert!(matches!(step(success), BehaviourStatus::Running(..)));
+        assert!(matches!(step(failure), BehaviourStatus::Failure));
+        // Starts over after a failure
+        assert!(matches!(step(success), BehaviourStatus::Running(..)));
+        assert!(matches!(step(success), BehaviourStatus::Success(..)));
+    }
+
+    #[test]
+    fn cooldown_skips_the_child() {
+        let mut blackboard = Blackboard::default();
+        let mut path = vec![0];
+        let mut step = |result: fn() -> BehaviourResult<BehaviourStatus>| {
+            blackboard.beat += 1;
+            let mut evaluated = false;
+            let status = cooldown_node(2, &mut blackboard, &mut path, |_, _| {
+                evaluated = true;
+                result()
+            })
+            .unwrap();
+            (evaluated, status)
+        };
+
+        // Beat 1: a failure does not start the cooldown
+        assert!(matches!(step(failure), (true, BehaviourStatus::Failure)));
+        // Beat 2
+        assert!(matches!(
+            step(success),
+            (true, BehaviourStatus::Success(..))
+        ));
+        // Beats 3 and 4 are on cooldown
+        assert!(matches!(step(success), (false, BehaviourStatus::Failure)));
+        assert!(matches!(step(success), (false, BehaviourStatus::Failure)));
+        // Beat 5
+        assert!(matches!(
+            step(success),
+            (true, BehaviourStatus::Success(..))
+        ));
     }
 }
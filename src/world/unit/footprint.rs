@@ -0,0 +1,76 @@
+use super::*;
+
+/// Cells occupied by a unit, relative to its grid position.
+/// Always has at least one cell.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "serder::FootprintSerde")]
+pub struct Footprint {
+    cells: Vec<vec2<Coord>>,
+}
+
+impl Footprint {
+    /// Occupies only the cell at the unit's position.
+    pub fn single() -> Self {
+        Self {
+            cells: vec![vec2::ZERO],
+        }
+    }
+
+    /// Returns the occupied cells when the unit is at `pos`.
+    pub fn cells(&self, pos: vec2<Coord>) -> impl Iterator<Item = vec2<Coord>> + '_ {
+        self.cells.iter().map(move |&cell| pos + cell)
+    }
+
+    pub fn contains(&self, pos: vec2<Coord>, cell: vec2<Coord>) -> bool {
+        self.cells.contains(&(cell - pos))
+    }
+
+    /// Returns the bounding box of the cells relative to the unit's position.
+    pub fn bounds(&self) -> Aabb2<Coord> {
+        let mut bounds = Aabb2::point(vec2::ZERO);
+        for &cell in &self.cells {
+            bounds.min = vec2(bounds.min.x.min(cell.x), bounds.min.y.min(cell.y));
+            bounds.max = vec2(bounds.max.x.max(cell.x), bounds.max.y.max(cell.y));
+        }
+        bounds
+    }
+}
+
+impl Default for Footprint {
+    fn default() -> Self {
+        Self::single()
+    }
+}
+
+/// Returns the unit whose footprint covers the cell.
+pub fn find_unit_at(units: &StructOf<Collection<Unit>>, cell: vec2<Coord>) -> Option<UnitId> {
+    units
+        .grid_position
+        .iter()
+        .find(|&(id, &pos)| {
+            let footprint = units.footprint.get(id).expect("Unit not found");
+            footprint.contains(pos, cell)
+        })
+        .map(|(id, _)| id)
+}
+
+mod serder {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct FootprintSerde {
+        cells: Vec<vec2<Coord>>,
+    }
+
+    impl TryFrom<FootprintSerde> for Footprint {
+        type Error = String;
+
+        fn try_from(value: FootprintSerde) -> Result<Self, Self::Error> {
+            if value.cells.is_empty() {
+                return Err("Footprint must have at least one cell".to_owned());
+            }
+            Ok(Self { cells: value.cells })
+        }
+    }
+}
@@ -1,21 +1,54 @@
 use super::*;
 
 mod behaviour;
+mod footprint;
 
 pub use behaviour::*;
+pub use footprint::*;
 
 pub type UnitId = Id;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UnitAI {
     pub beat: UnitBeat,
     /// Normalized (in range 0..1) time until the next beat.
+    #[serde(skip, default = "UnitAI::first_beat")]
     pub next_beat: Time,
     pub behaviour: UnitBehaviour,
+    #[serde(skip)]
+    pub blackboard: Blackboard,
+    /// Action decided a beat ahead, telegraphed to the player.
+    #[serde(skip)]
+    pub intent: Option<UnitIntent>,
+    /// The unit followed by `UnitBehaviour::Leash`, e.g. the summoner.
+    #[serde(skip)]
+    pub leader: Option<UnitId>,
+    /// Phases yet to come, in order of decreasing `health_ratio`.
+    #[serde(default)]
+    pub phases: Vec<UnitPhase>,
 }
 
-/// Describes how often the unit makes decisions.
+/// Behaviour that the unit switches to when its health drops, e.g. a boss phase.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitPhase {
+    /// Health ratio (in range 0..1) at or below which the phase starts.
+    pub health_ratio: R32,
+    pub beat: UnitBeat,
+    pub behaviour: UnitBehaviour,
+}
+
+/// Action that the unit is going to take on its next beat.
 #[derive(Debug, Clone)]
+pub struct UnitIntent {
+    pub action: UnitAction,
+    pub input: ActionInput,
+}
+
+/// Describes how often the unit makes decisions.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum UnitBeat {
     /// Beats once every `player / unit` player's beats.
     Synchronized {
@@ -24,11 +57,25 @@ pub enum UnitBeat {
         /// How many player's beats should pass.
         player: Ticks,
         /// Current beat index modulo `player`.
+        #[serde(skip)]
         current_beat: Ticks,
     },
     Independent {
         bpm: Ticks,
     },
+    /// Beats on the first beat of every `bars` bars of the music.
+    Bars {
+        bars: Ticks,
+        /// The last bar the unit has beaten on.
+        #[serde(skip)]
+        last_bar: Option<Ticks>,
+    },
+    /// Beats on the music ticks whose index modulo `cycle` is in `ticks`,
+    /// e.g. `cycle: 4, ticks: [2]` for off-beats with 4 ticks per beat.
+    MusicTicks {
+        cycle: Ticks,
+        ticks: Vec<Ticks>,
+    },
 }
 
 #[derive(StructOf)]
@@ -37,6 +84,122 @@ pub struct Unit {
     pub fraction: Fraction,
     pub grid_position: vec2<Coord>,
     pub world_position: vec2<FCoord>,
+    pub footprint: Footprint,
     pub health: Health,
+    /// Damage recently dealt by the unit, decays over time.
+    pub threat: Hp,
+    pub held_items: HeldItems,
+}
+
+/// Description of a unit to be spawned, e.g. by a summon.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitPrefab {
+    pub ai: Option<UnitAI>,
+    #[serde(default)]
+    pub footprint: Footprint,
+    pub max_health: Hp,
     pub held_items: HeldItems,
 }
+
+/// Unit prefabs by name, loaded from `units.json`.
+#[derive(Debug, Clone, Deserialize, geng::Load)]
+#[load(json)]
+#[serde(try_from = "serder::UnitPrefabsSerde")]
+pub struct UnitPrefabs(HashMap<String, UnitPrefab>);
+
+#[derive(Debug, Clone)]
+pub struct UnitInst {
+    pub grid_position: vec2<Coord>,
+    pub world_position: vec2<FCoord>,
+    pub fraction: Fraction,
+    pub leader: Option<UnitId>,
+}
+
+impl UnitAI {
+    /// Units wait for a whole beat before acting for the first time.
+    fn first_beat() -> Time {
+        Time::ONE
+    }
+}
+
+impl UnitPrefabs {
+    pub fn get(&self, name: &str) -> Option<&UnitPrefab> {
+        self.0.get(name)
+    }
+}
+
+impl UnitPrefab {
+    /// Checks whether all cells of the footprint at `pos` are free.
+    pub fn fits(&self, units: &StructOf<Collection<Unit>>, pos: vec2<Coord>) -> bool {
+        self.footprint
+            .cells(pos)
+            .all(|cell| find_unit_at(units, cell).is_none())
+    }
+
+    /// Substitutes the named behaviours of the unit, its phases and its items.
+    pub fn resolve_behaviours(
+        &mut self,
+        behaviours: &HashMap<BehaviourName, UnitBehaviour>,
+    ) -> Result<(), String> {
+        if let Some(ai) = &mut self.ai {
+            ai.behaviour.resolve(behaviours)?;
+            for phase in &mut ai.phases {
+                phase.behaviour.resolve(behaviours)?;
+            }
+        }
+        for item in [
+            &mut self.held_items.left_hand,
+            &mut self.held_items.right_hand,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            item.on_use.effect.resolve_behaviours(behaviours)?;
+        }
+        Ok(())
+    }
+
+    pub fn instantiate(self, inst: UnitInst) -> Unit {
+        Unit {
+            unit: self.ai.map(|ai| UnitAI {
+                leader: inst.leader,
+                ..ai
+            }),
+            fraction: inst.fraction,
+            grid_position: inst.grid_position,
+            world_position: inst.world_position,
+            footprint: self.footprint,
+            health: Health::new(self.max_health),
+            threat: Hp::ZERO,
+            held_items: self.held_items,
+        }
+    }
+}
+
+mod serder {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct UnitPrefabsSerde {
+        /// Behaviours shared between the units, referenced by `UnitBehaviour::Named`.
+        #[serde(default)]
+        behaviours: HashMap<BehaviourName, UnitBehaviour>,
+        units: HashMap<String, UnitPrefab>,
+    }
+
+    impl TryFrom<UnitPrefabsSerde> for UnitPrefabs {
+        type Error = String;
+
+        fn try_from(value: UnitPrefabsSerde) -> Result<Self, Self::Error> {
+            let mut units = value.units;
+            for (name, prefab) in &mut units {
+                prefab
+                    .resolve_behaviours(&value.behaviours)
+                    .map_err(|err| format!("Unit {name:?}: {err}"))?;
+            }
+            Ok(Self(units))
+        }
+    }
+}